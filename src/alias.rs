@@ -0,0 +1,337 @@
+use crate::document::DocumentData;
+use crate::error::{Error, Result};
+use crate::paths::Location;
+use crate::profile::{AliasProfile, AliasRule, LayoutProfile, Profile};
+use crate::util::list_dirs;
+use glob::{MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tokio::fs;
+
+const SIMILARITY_THRESHOLD: f64 = 0.8;
+
+/// The patterns of an `AliasRule`, compiled once by `Profile::check`
+/// or on first use.
+#[derive(Clone, Debug, Default)]
+pub struct CompiledAliases(OnceLock<(Vec<Pattern>, Vec<Regex>)>);
+
+/// Compiled patterns are derived from the rule, so they don't take part in comparisons.
+impl PartialEq for CompiledAliases {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for CompiledAliases {}
+
+impl AliasRule {
+    /// Compiles the aliases as globs, falling back to the literal name,
+    /// and the patterns as case-insensitive regular expressions.
+    fn compile(&self) -> Result<(Vec<Pattern>, Vec<Regex>)> {
+        let globs = std::iter::once(&self.canonical)
+            .chain(&self.aliases)
+            .map(|alias| Pattern::new(alias).or_else(|_| Pattern::new(&Pattern::escape(alias))))
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|e| Error::Other(format!("Invalid alias {:?}: {e}", self.canonical)))?;
+        let regexes = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| Error::Other(format!("Invalid alias pattern {pattern:?}: {e}")))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok((globs, regexes))
+    }
+
+    pub fn check(&self) -> Result<()> {
+        if self.compiled.0.get().is_none() {
+            let _ = self.compiled.0.set(self.compile()?);
+        }
+
+        Ok(())
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        let (globs, regexes) = self.compiled.0.get_or_init(|| {
+            self.compile()
+                .inspect_err(|e| log::warn!("Ignoring aliases of {:?}: {e}", self.canonical))
+                .unwrap_or_default()
+        });
+        globs.iter().any(|glob| glob.matches_with(value, options))
+            || regexes.iter().any(|regex| regex.is_match(value))
+    }
+}
+
+fn canonicalize(rules: &[AliasRule], value: &str) -> String {
+    rules
+        .iter()
+        .find(|rule| rule.matches(value))
+        .map_or_else(|| value.to_string(), |rule| rule.canonical.clone())
+}
+
+impl AliasProfile {
+    pub fn check(&self) -> Result<()> {
+        self.classes
+            .iter()
+            .chain(&self.sources)
+            .try_for_each(AliasRule::check)
+    }
+
+    pub fn canonical_class(&self, class: &str) -> String {
+        canonicalize(&self.classes, class)
+    }
+
+    pub fn canonical_source(&self, source: &str) -> String {
+        canonicalize(&self.sources, source)
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn is_similar(a: &str, b: &str) -> bool {
+    let (a, b) = (normalize(a), normalize(b));
+    if a.is_empty() || b.is_empty() {
+        return false;
+    }
+    let (shorter, longer) = if a.len() <= b.len() {
+        (&a, &b)
+    } else {
+        (&b, &a)
+    };
+    a == b
+        || (shorter.len() >= 4 && longer.contains(shorter.as_str()))
+        || strsim::normalized_levenshtein(&a, &b) >= SIMILARITY_THRESHOLD
+}
+
+/// Groups similar names into merge proposals. The name with the most documents
+/// (or the shortest one on a tie) is proposed as canonical.
+pub fn propose_merges(counts: &BTreeMap<String, usize>) -> Vec<AliasRule> {
+    let mut names: Vec<_> = counts.keys().cloned().collect();
+    names.sort_by_key(|name| (std::cmp::Reverse(counts[name]), name.len(), name.clone()));
+
+    let mut proposals: Vec<AliasRule> = Vec::new();
+    for name in names {
+        match proposals.iter_mut().find(|rule| {
+            std::iter::once(&rule.canonical)
+                .chain(&rule.aliases)
+                .any(|other| is_similar(other, &name))
+        }) {
+            Some(rule) => rule.aliases.push(name),
+            None => proposals.push(AliasRule {
+                canonical: name,
+                aliases: Vec::new(),
+                patterns: Vec::new(),
+                compiled: CompiledAliases::default(),
+            }),
+        }
+    }
+
+    proposals.retain(|rule| !rule.aliases.is_empty());
+    proposals
+}
+
+async fn count_files(path: &Path) -> Result<usize> {
+    let mut count = 0;
+    let mut entries = fs::read_dir(path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.metadata().await?.is_file() {
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// The outbox is treated as a tree of class and source directories,
+/// which only holds for the default layout path.
+fn check_layout(layout: &LayoutProfile) -> Result<()> {
+    let default = LayoutProfile::default().path;
+    if layout.path != default {
+        return Err(Error::Other(format!(
+            "Aliases can only be applied to the layout path {default:?}, not {:?}",
+            layout.path
+        )));
+    }
+
+    Ok(())
+}
+
+/// Checks whether both paths refer to the same existing directory,
+/// e.g. on case-insensitive filesystems.
+#[cfg(unix)]
+async fn is_same_dir(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a).await, fs::metadata(b).await) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+async fn is_same_dir(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a).await, fs::canonicalize(b).await) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Counts the documents per class and per source directory in the outbox.
+pub async fn scan_outbox(
+    profile: &Profile,
+) -> Result<(BTreeMap<String, usize>, BTreeMap<String, usize>)> {
+    check_layout(&profile.layout)?;
+    let outbox = profile.paths.make_root(Location::Outbox);
+    let mut classes = BTreeMap::new();
+    let mut sources = BTreeMap::new();
+    for class in list_dirs(&outbox).await? {
+        for source in list_dirs(&outbox.join(&class)).await? {
+            let count = count_files(&outbox.join(&class).join(&source)).await?;
+            *classes.entry(class.clone()).or_default() += count;
+            *sources.entry(source).or_default() += count;
+        }
+    }
+
+    Ok((classes, sources))
+}
+
+/// Determines the outbox directories whose names are not canonical according to
+/// the aliases, along with the directory their contents belong in. The target is
+/// built from the layout exactly as for newly filed documents.
+pub async fn plan_renames(profile: &Profile) -> Result<Vec<(PathBuf, PathBuf)>> {
+    check_layout(&profile.layout)?;
+    let outbox = profile.paths.make_root(Location::Outbox);
+    let mut renames = Vec::new();
+    for class in list_dirs(&outbox).await? {
+        for source in list_dirs(&outbox.join(&class)).await? {
+            let document_data = DocumentData {
+                class: profile.aliases.canonical_class(&class),
+                source: profile.aliases.canonical_source(&source),
+                ..DocumentData::default()
+            };
+            let from = outbox.join(&class).join(&source);
+            let to = outbox.join(document_data.make_path(&profile.layout)?);
+            if from != to && !is_same_dir(&from, &to).await {
+                renames.push((from, to));
+            }
+        }
+    }
+
+    Ok(renames)
+}
+
+async fn remove_if_empty(path: &Path) -> Result<()> {
+    if fs::read_dir(path).await?.next_entry().await?.is_none() {
+        fs::remove_dir(path).await?;
+    }
+
+    Ok(())
+}
+
+/// Moves the files of `from` into `to`, merging with any existing contents.
+/// Files whose name is already taken in `to` are left in place.
+pub async fn merge_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).await?;
+    let mut entries = fs::read_dir(from).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let target = to.join(entry.file_name());
+        if fs::try_exists(&target).await? {
+            log::warn!("Not moving {:?}, {target:?} already exists", entry.path());
+            continue;
+        }
+        fs::rename(entry.path(), target).await?;
+    }
+    remove_if_empty(from).await?;
+    if let Some(parent) = from.parent() {
+        remove_if_empty(parent).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn make_aliases() -> AliasProfile {
+        serde_yaml::from_str(
+            "sources:\n  - canonical: telekom\n    aliases: [deutsche-telekom, telekom-*]\n  - canonical: stadtwerke\n    patterns: ['^stadtwerke?-']\n",
+        )
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case("telekom", "telekom")]
+    #[case("Telekom", "telekom")]
+    #[case("Deutsche-Telekom", "telekom")]
+    #[case("telekom-ag", "telekom")]
+    #[case("stadtwerk-musterstadt", "stadtwerke")]
+    #[case("vodafone", "vodafone")]
+    fn test_canonical_source(#[case] source: &str, #[case] canonical: &str) {
+        assert_eq!(make_aliases().canonical_source(source), canonical);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_plan_renames() {
+        let tmp = tempdir::TempDir::new("mrdocument-test").unwrap();
+        let mut profile = Profile::default();
+        profile.paths = profile.paths.with_path(tmp.path().to_path_buf());
+        profile.aliases = serde_yaml::from_str(
+            "sources:\n  - canonical: Telekom\n    aliases: [deutsche-telekom]\n",
+        )
+        .unwrap();
+        let outbox = profile.paths.make_root(Location::Outbox);
+        for dir in [
+            "invoice/telekom",
+            "invoice/deutsche-telekom",
+            "invoice/vodafone",
+        ] {
+            fs::create_dir_all(outbox.join(dir)).await.unwrap();
+        }
+
+        assert_eq!(
+            plan_renames(&profile).await.unwrap(),
+            vec![(
+                outbox.join("invoice/deutsche-telekom"),
+                outbox.join("invoice/telekom")
+            )]
+        );
+
+        profile.layout.path = "{year}/{class}".to_string();
+        assert!(plan_renames(&profile).await.is_err());
+    }
+
+    #[rstest]
+    fn test_propose_merges() {
+        let counts = BTreeMap::from([
+            ("invoice".to_string(), 10),
+            ("invoices".to_string(), 2),
+            ("telekom".to_string(), 3),
+            ("deutsche-telekom".to_string(), 1),
+            ("contract".to_string(), 4),
+        ]);
+        let proposals = propose_merges(&counts);
+        assert_eq!(proposals.len(), 2);
+        assert_eq!(proposals[0].canonical, "invoice");
+        assert_eq!(proposals[0].aliases, vec!["invoices".to_string()]);
+        assert_eq!(proposals[1].canonical, "telekom");
+        assert_eq!(proposals[1].aliases, vec!["deutsche-telekom".to_string()]);
+    }
+}
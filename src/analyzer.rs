@@ -0,0 +1,129 @@
+use crate::anthropic::AnthropicAnalyzer;
+use crate::chatgpt::ChatGptAnalyzer;
+use crate::chunked::ChunkedAnalyzer;
+use crate::document::DocumentData;
+use crate::error::Result;
+use crate::file_info::Page;
+use crate::profile::{BackendProfile, ChatGptProfile, Profile};
+use crate::prompt::Prompt;
+use crate::retry::RetryAnalyzer;
+use crate::tesseract::TesseractAnalyzer;
+use crate::validation::Validator;
+use chrono::{DateTime, Local};
+use std::future::Future;
+use std::pin::Pin;
+
+#[derive(Clone, Debug, Default)]
+pub struct DocumentContext {
+    pub classes: Vec<String>,
+    pub sources: Vec<String>,
+    pub filename: String,
+    pub mtime: Option<DateTime<Local>>,
+    pub sender: Option<String>,
+}
+
+pub trait DocumentAnalyzer: Send + Sync + 'static {
+    fn analyze(
+        &self,
+        pages: Vec<Page>,
+        context: DocumentContext,
+    ) -> impl Future<Output = Result<DocumentData>> + Send;
+
+    /// Transcribes `pages` completely, without classifying them.
+    fn transcribe(&self, pages: Vec<Page>) -> impl Future<Output = Result<String>> + Send;
+}
+
+pub enum Analyzer {
+    ChatGpt(ChatGptAnalyzer),
+    Anthropic(AnthropicAnalyzer),
+    Tesseract(TesseractAnalyzer),
+    Chunked(ChunkedAnalyzer),
+    Retry(RetryAnalyzer),
+}
+
+impl Analyzer {
+    pub fn new(profile: &Profile) -> Result<Self> {
+        let analyzer = Analyzer::Retry(RetryAnalyzer::new(
+            profile.retry.clone(),
+            Analyzer::new_from_backend(
+                &profile.backend,
+                &profile.chatgpt,
+                &Prompt::new(profile)?,
+                &Validator::new(profile),
+            ),
+        ));
+        Ok(match profile.chunking {
+            Some(ref chunking) => Analyzer::Chunked(ChunkedAnalyzer::new(
+                chunking.clone(),
+                profile.retry.clone(),
+                analyzer,
+            )),
+            None => analyzer,
+        })
+    }
+
+    pub fn analyze_boxed(
+        &self,
+        pages: Vec<Page>,
+        context: DocumentContext,
+    ) -> Pin<Box<dyn Future<Output = Result<DocumentData>> + Send + '_>> {
+        Box::pin(self.analyze(pages, context))
+    }
+
+    pub fn transcribe_boxed(
+        &self,
+        pages: Vec<Page>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + '_>> {
+        Box::pin(self.transcribe(pages))
+    }
+
+    fn new_from_backend(
+        backend: &BackendProfile,
+        chatgpt: &ChatGptProfile,
+        prompt: &Prompt,
+        validator: &Validator,
+    ) -> Self {
+        match backend {
+            BackendProfile::OpenAi(backend) => Analyzer::ChatGpt(ChatGptAnalyzer::new(
+                chatgpt.clone(),
+                backend.clone(),
+                prompt.clone(),
+                validator.clone(),
+            )),
+            BackendProfile::Anthropic(backend) => Analyzer::Anthropic(AnthropicAnalyzer::new(
+                chatgpt.clone(),
+                backend.clone(),
+                prompt.clone(),
+                validator.clone(),
+            )),
+            BackendProfile::Tesseract(backend) => Analyzer::Tesseract(TesseractAnalyzer::new(
+                backend.clone(),
+                backend.classifier.as_ref().map(|classifier| {
+                    Analyzer::new_from_backend(classifier, chatgpt, prompt, validator)
+                }),
+            )),
+        }
+    }
+}
+
+impl DocumentAnalyzer for Analyzer {
+    async fn analyze(&self, pages: Vec<Page>, context: DocumentContext) -> Result<DocumentData> {
+        match self {
+            Analyzer::ChatGpt(analyzer) => analyzer.analyze(pages, context).await,
+            Analyzer::Anthropic(analyzer) => analyzer.analyze(pages, context).await,
+            Analyzer::Tesseract(analyzer) => analyzer.analyze(pages, context).await,
+            Analyzer::Chunked(analyzer) => analyzer.analyze(pages, context).await,
+            Analyzer::Retry(analyzer) => analyzer.analyze(pages, context).await,
+        }
+    }
+
+    async fn transcribe(&self, pages: Vec<Page>) -> Result<String> {
+        match self {
+            Analyzer::ChatGpt(analyzer) => analyzer.transcribe(pages).await,
+            Analyzer::Anthropic(analyzer) => analyzer.transcribe(pages).await,
+            Analyzer::Tesseract(analyzer) => analyzer.transcribe(pages).await,
+            Analyzer::Chunked(analyzer) => analyzer.transcribe(pages).await,
+            Analyzer::Retry(analyzer) => analyzer.transcribe(pages).await,
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use crate::analyzer::{DocumentAnalyzer, DocumentContext};
+use crate::api_key;
+use crate::document::DocumentData;
+use crate::error::{Error, Result};
+use crate::file_info::Page;
+use crate::http;
+use crate::profile::{AnthropicBackend, ChatGptProfile};
+use crate::prompt::{
+    transcription_function, Prompt, Transcription, DOCUMENT_DATA_FUNCTION, TRANSCRIPTION_FUNCTION,
+    TRANSCRIPTION_PROMPT,
+};
+use crate::validation::{make_repair_message, ValidationOutcome, Validator};
+use serde::Deserialize;
+use serde_json::json;
+
+const API_URL: &str = "https://api.anthropic.com";
+const API_VERSION: &str = "2023-06-01";
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+fn default_tools(prompt: &Prompt) -> Vec<serde_json::Value> {
+    let function = prompt.document_data_function();
+    vec![json!({
+        "name": function["name"],
+        "description": function["description"],
+        "input_schema": function["parameters"],
+    })]
+}
+
+fn make_content(page: Page) -> serde_json::Value {
+    match page {
+        Page::Text(text) => json!({ "type": "text", "text": text }),
+        Page::Image { mime_type, data } => json!({
+            "type": "image",
+            "source": {
+                "type": "base64",
+                "media_type": mime_type,
+                "data": data,
+            }
+        }),
+    }
+}
+
+/// The Messages API only accepts temperatures up to 1.0.
+fn temperature(profile: &ChatGptProfile) -> f64 {
+    profile.api_temperature().min(1.0)
+}
+
+/// Sends `req` to the Messages API and returns the raw response.
+async fn send(backend: &AnthropicBackend, req: &serde_json::Value) -> Result<serde_json::Value> {
+    let api_key = api_key::get_anthropic()?;
+    post(backend.api_base.as_deref().unwrap_or(API_URL), api_key, req).await
+}
+
+async fn post(api_base: &str, api_key: &str, req: &serde_json::Value) -> Result<serde_json::Value> {
+    let request = reqwest::Client::new()
+        .post(format!("{}/v1/messages", api_base.trim_end_matches('/')))
+        .header("x-api-key", api_key)
+        .header("anthropic-version", API_VERSION)
+        .json(req);
+
+    Ok(serde_json::from_str(&http::send(request).await?)?)
+}
+
+/// Returns the ID and input of the first use of the tool `tool` in `raw`.
+fn find_tool_use(raw: &serde_json::Value, tool: &str) -> Result<(String, serde_json::Value)> {
+    let response: MessagesResponse = serde_json::from_value(raw.clone())?;
+    response
+        .content
+        .into_iter()
+        .find_map(|block| match block {
+            ContentBlock::ToolUse { id, name, input } if name == tool => Some((id, input)),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            log::debug!("No tool use in response: {raw}");
+            Error::DoesNotProcessError(None)
+        })
+}
+
+/// Requests a plain transcription of `pages`, without any classification.
+pub async fn query_transcription(
+    profile: &ChatGptProfile,
+    backend: &AnthropicBackend,
+    pages: Vec<Page>,
+) -> Result<String> {
+    log::info!("Received {} pages for transcription", pages.len());
+    let function = transcription_function();
+    let content: Vec<_> = pages.into_iter().map(make_content).collect();
+    let req = json!({
+        "model": profile.model,
+        "max_tokens": backend.max_tokens,
+        "temperature": temperature(profile),
+        "system": TRANSCRIPTION_PROMPT,
+        "tools": [{
+            "name": function["name"],
+            "description": function["description"],
+            "input_schema": function["parameters"],
+        }],
+        "tool_choice": { "type": "tool", "name": TRANSCRIPTION_FUNCTION },
+        "messages": [{ "role": "user", "content": content }],
+    });
+
+    log::info!("Sending transcription request to {}", profile.model);
+    let raw = send(backend, &req).await?;
+    let (_, input) = find_tool_use(&raw, TRANSCRIPTION_FUNCTION)?;
+    let transcription: Transcription = serde_json::from_value(input)?;
+
+    Ok(transcription.content)
+}
+
+pub async fn query_ai(
+    profile: ChatGptProfile,
+    backend: &AnthropicBackend,
+    prompt: &Prompt,
+    validator: &Validator,
+    pages: Vec<Page>,
+    context: DocumentContext,
+) -> Result<DocumentData> {
+    log::info!("Received {} pages", pages.len());
+    let temperature = temperature(&profile);
+    let system = std::iter::once(prompt.make_system_prompt(&context)?)
+        .chain(profile.additional_instructions)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    log::debug!("Using instructions: {system:?}");
+    let content: Vec<_> = prompt
+        .make_hints(&context)
+        .map(Page::Text)
+        .into_iter()
+        .chain(pages)
+        .map(make_content)
+        .collect();
+    let mut messages = vec![json!({ "role": "user", "content": content })];
+    let mut repaired = Vec::new();
+    let mut round = 0;
+    loop {
+        let req = json!({
+            "model": profile.model,
+            "max_tokens": backend.max_tokens,
+            "temperature": temperature,
+            "system": system,
+            "tools": default_tools(prompt),
+            "tool_choice": { "type": "tool", "name": DOCUMENT_DATA_FUNCTION },
+            "messages": messages,
+        });
+
+        log::info!("Sending request to {}", profile.model);
+        let raw = send(backend, &req).await?;
+        let (id, input) = find_tool_use(&raw, DOCUMENT_DATA_FUNCTION)?;
+
+        let errors = match validator.validate(input, &context) {
+            Ok(mut document_data) => {
+                document_data.validation = ValidationOutcome {
+                    rounds: round,
+                    repaired,
+                    ..document_data.validation
+                };
+                return Ok(document_data);
+            }
+            Err(errors) => errors,
+        };
+        if round >= validator.repair_rounds() {
+            return Err(Error::ValidationError(errors));
+        }
+
+        log::warn!(
+            "Invalid response, requesting repair ({}/{}): {}",
+            round + 1,
+            validator.repair_rounds(),
+            errors.join("; ")
+        );
+        messages.push(json!({ "role": "assistant", "content": raw["content"] }));
+        messages.push(json!({
+            "role": "user",
+            "content": [{
+                "type": "tool_result",
+                "tool_use_id": id,
+                "is_error": true,
+                "content": make_repair_message(&errors),
+            }],
+        }));
+        repaired.extend(errors);
+        round += 1;
+    }
+}
+
+pub struct AnthropicAnalyzer {
+    profile: ChatGptProfile,
+    backend: AnthropicBackend,
+    prompt: Prompt,
+    validator: Validator,
+}
+
+impl AnthropicAnalyzer {
+    pub fn new(
+        profile: ChatGptProfile,
+        backend: AnthropicBackend,
+        prompt: Prompt,
+        validator: Validator,
+    ) -> Self {
+        AnthropicAnalyzer {
+            profile,
+            backend,
+            prompt,
+            validator,
+        }
+    }
+}
+
+impl DocumentAnalyzer for AnthropicAnalyzer {
+    async fn analyze(&self, pages: Vec<Page>, context: DocumentContext) -> Result<DocumentData> {
+        query_ai(
+            self.profile.clone(),
+            &self.backend,
+            &self.prompt,
+            &self.validator,
+            pages,
+            context,
+        )
+        .await
+    }
+
+    async fn transcribe(&self, pages: Vec<Page>) -> Result<String> {
+        query_transcription(&self.profile, &self.backend, pages).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::serve_once;
+    use rstest::rstest;
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let api_base = serve_once(
+            "HTTP/1.1 429 Too Many Requests
+Retry-After: 7",
+            r#"{"error": {"message": "Rate limit hit"}}"#,
+        )
+        .await;
+        match post(&api_base, "key", &json!({})).await {
+            Err(err @ Error::RateLimitError(..)) => {
+                assert_eq!(err.retry_after(), Some(std::time::Duration::from_secs(7)));
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+}
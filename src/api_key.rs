@@ -2,26 +2,46 @@ use crate::error::{Error, Result};
 use crate::profile::Profile;
 use once_cell::sync::OnceCell;
 use std::env;
+use std::path::Path;
 
-static OPENAI_API_KEY: OnceCell<String> = OnceCell::new();
+static OPENAI_API_KEY: OnceCell<Option<String>> = OnceCell::new();
+static ANTHROPIC_API_KEY: OnceCell<Option<String>> = OnceCell::new();
+
+fn read_key(config_dir: &Path, var: &str, filename: &str) -> Result<String> {
+    env::var(var)
+        .map(|key| key.to_string())
+        .or_else(|_: env::VarError| {
+            let key = std::fs::read_to_string(config_dir.join(filename))?;
+            Ok(key.trim_end_matches("\n").to_string())
+        })
+}
 
 pub fn init() -> Result<()> {
     let config_dir = Profile::get_config_dir()?;
-    OPENAI_API_KEY
-        .set(
-            env::var("OPENAI_API_KEY")
-                .map(|key| key.to_string())
-                .or_else(|_: env::VarError| {
-                    let key = std::fs::read_to_string(config_dir.join("openai-api-key"))?;
-                    Ok(key.trim_end_matches("\n").to_string())
-                })
-                .map_err(|_: Error| Error::NoApiKeyError)?,
-        )
-        .unwrap();
+    let openai_key = read_key(&config_dir, "OPENAI_API_KEY", "openai-api-key")
+        .inspect_err(|_| {
+            log::warn!("No OpenAI API key provided, only profiles without API key will work")
+        })
+        .ok();
+    OPENAI_API_KEY.set(openai_key).unwrap();
+    let anthropic_key = read_key(&config_dir, "ANTHROPIC_API_KEY", "anthropic-api-key")
+        .inspect_err(|_| log::debug!("No Anthropic API key provided"))
+        .ok();
+    ANTHROPIC_API_KEY.set(anthropic_key).unwrap();
 
     Ok(())
 }
 
-pub fn get() -> &'static String {
-    OPENAI_API_KEY.get().unwrap()
+pub fn get() -> Result<&'static String> {
+    OPENAI_API_KEY
+        .get()
+        .and_then(|key| key.as_ref())
+        .ok_or(Error::NoApiKeyError)
+}
+
+pub fn get_anthropic() -> Result<&'static String> {
+    ANTHROPIC_API_KEY
+        .get()
+        .and_then(|key| key.as_ref())
+        .ok_or(Error::NoApiKeyError)
 }
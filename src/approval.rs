@@ -0,0 +1,280 @@
+use crate::document::DocumentData;
+use crate::error::{Error, Result};
+use crate::paths::Location;
+use crate::profile::Profile;
+use crate::sidecar::{complete, fail, make_original_path, make_path, strip_suffix, write_original};
+use crate::util::run_scheduler;
+use crate::validation::{ValidationOutcome, Validator};
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::time::Duration;
+
+const SUFFIX: &str = ".proposal.yaml";
+/// Keeps the outcome of the original validation, which is not part of the proposal.
+const VALIDATION_SUFFIX: &str = ".validation.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Proposal {
+    #[serde(default)]
+    approved: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    problems: Vec<String>,
+    #[serde(flatten)]
+    document_data: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Proposal {
+    fn new(document_data: &DocumentData) -> Result<Self> {
+        let mut document_data = match serde_json::to_value(document_data)? {
+            serde_json::Value::Object(document_data) => document_data,
+            _ => unreachable!("DocumentData serializes to an object"),
+        };
+        document_data.remove("validation");
+
+        Ok(Proposal {
+            approved: false,
+            problems: Vec::new(),
+            document_data,
+        })
+    }
+}
+
+async fn write(proposal_path: &Path, proposal: &Proposal) -> Result<()> {
+    Ok(fs::write(proposal_path, serde_yaml::to_string(proposal)?).await?)
+}
+
+/// Stores `document_data` as a proposal next to the pending file at `file_path`,
+/// along with its validation outcome and its `original`, see `file_document`.
+pub async fn propose(
+    file_path: &Path,
+    document_data: &DocumentData,
+    original: Option<&str>,
+) -> Result<PathBuf> {
+    let proposal_path = make_path(file_path, SUFFIX);
+    write_original(file_path, original).await?;
+    fs::write(
+        make_path(file_path, VALIDATION_SUFFIX),
+        serde_json::to_string_pretty(&document_data.validation)?,
+    )
+    .await?;
+    write(&proposal_path, &Proposal::new(document_data)?).await?;
+
+    Ok(proposal_path)
+}
+
+async fn read_validation(validation_path: &Path) -> Result<ValidationOutcome> {
+    match fs::read_to_string(validation_path).await {
+        Ok(outcome) => Ok(serde_json::from_str(&outcome)?),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(ValidationOutcome::default()),
+        Err(err) => Err(Error::from(err)),
+    }
+}
+
+/// Validates the approved proposal, keeping the outcome of the original validation
+/// stored at `validation_path`. If the corrected values are not acceptable,
+/// the proposal is written back unapproved with the problems listed.
+async fn check(
+    validator: &Validator,
+    proposal_path: &Path,
+    validation_path: &Path,
+    proposal: Proposal,
+) -> Result<Option<DocumentData>> {
+    let outcome = read_validation(validation_path).await?;
+    match validator.revalidate(
+        serde_json::Value::Object(proposal.document_data.clone()),
+        outcome,
+    ) {
+        Ok(document_data) => Ok(Some(document_data)),
+        Err(problems) => {
+            log::warn!(
+                "Proposal {proposal_path:?} is not acceptable: {}",
+                problems.join("; ")
+            );
+            write(
+                proposal_path,
+                &Proposal {
+                    approved: false,
+                    problems,
+                    ..proposal
+                },
+            )
+            .await?;
+            Ok(None)
+        }
+    }
+}
+
+async fn complete_approved(profile: &Profile) -> Result<()> {
+    let validator = Validator::new(profile);
+    let mut entries = fs::read_dir(profile.paths.make_root(Location::Pending)).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let proposal_path = entry.path();
+        let Some(file_path) = strip_suffix(&proposal_path, SUFFIX) else {
+            continue;
+        };
+        let proposal = match fs::read_to_string(&proposal_path).await {
+            Ok(proposal) => serde_yaml::from_str::<Proposal>(&proposal).map_err(Error::from),
+            Err(err) => Err(Error::from(err)),
+        };
+        let proposal = match proposal {
+            Ok(proposal) => proposal,
+            Err(err) => {
+                log::warn!("Unable to read proposal {proposal_path:?}: {err}");
+                continue;
+            }
+        };
+        if !proposal.approved {
+            continue;
+        }
+        let validation_path = make_path(&file_path, VALIDATION_SUFFIX);
+        let document_data =
+            match check(&validator, &proposal_path, &validation_path, proposal).await {
+                Ok(Some(document_data)) => document_data,
+                Ok(None) => continue,
+                Err(err) => {
+                    log::warn!("Unable to update proposal {proposal_path:?}: {err}");
+                    continue;
+                }
+            };
+
+        log::info!("Completing approved document {file_path:?}");
+        let original_path = make_original_path(&file_path);
+        if let Err(err) = complete(profile, &file_path, &document_data, &original_path).await {
+            log::error!("Unable to complete approved document: {file_path:?}: {err}");
+            fail(profile, &file_path, &err, &original_path).await;
+        }
+        for path in [&proposal_path, &validation_path] {
+            fs::remove_file(path)
+                .await
+                .inspect_err(|e| log::warn!("Unable to remove {path:?}: {e}"))
+                .ok();
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_approval_scheduler(profile: Profile) {
+    run_scheduler(
+        Duration::from_secs(10),
+        "complete approved documents",
+        || complete_approved(&profile),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::collections::BTreeMap;
+
+    #[rstest]
+    fn test_proposal_round_trip() {
+        let document_data = DocumentData {
+            content: Some("Line 1\nLine 2".to_string()),
+            summary: "Foo".to_string(),
+            summary_translation: None,
+            class: "invoice".to_string(),
+            source: "stadtwerke".to_string(),
+            keywords: vec!["electricity".to_string()],
+            title: "electricity-march".to_string(),
+            date: "2024-03-01".to_string(),
+            extra: BTreeMap::new(),
+            confidence: BTreeMap::new(),
+            email: None,
+            validation: ValidationOutcome::default(),
+        };
+        let proposal = serde_yaml::to_string(&Proposal::new(&document_data).unwrap()).unwrap();
+        assert!(proposal.starts_with("approved: false\n"));
+        assert!(!proposal.contains("validation"));
+
+        let edited = proposal
+            .replace("approved: false", "approved: true")
+            .replace("stadtwerke", "swm");
+        let proposal: Proposal = serde_yaml::from_str(&edited).unwrap();
+        assert!(proposal.approved);
+        let document_data = Validator::new(&Profile::default())
+            .revalidate(
+                serde_json::Value::Object(proposal.document_data),
+                ValidationOutcome::default(),
+            )
+            .unwrap();
+        assert_eq!(document_data.source, "swm");
+        assert_eq!(document_data.content.as_deref(), Some("Line 1\nLine 2"));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_complete_approved_continues() {
+        let tmp = tempdir::TempDir::new("mrdocument-test").unwrap();
+        let mut profile = Profile::default();
+        profile.paths = profile.paths.with_path(tmp.path().to_path_buf());
+        let pending = profile.paths.make_root(Location::Pending);
+        fs::create_dir_all(pending.join("unreadable.pdf.proposal.yaml"))
+            .await
+            .unwrap();
+        let proposal = "summary: Foo\nclass: invoice\nsource: stadtwerke\nkeywords: [electricity]\ntitle: electricity-march\ndate: 2024-03-01\n";
+        fs::write(
+            pending.join("missing.pdf.proposal.yaml"),
+            format!("approved: true\n{proposal}"),
+        )
+        .await
+        .unwrap();
+        fs::write(pending.join("waiting.pdf.proposal.yaml"), proposal)
+            .await
+            .unwrap();
+
+        complete_approved(&profile).await.unwrap();
+        assert!(!pending.join("missing.pdf.proposal.yaml").exists());
+        assert!(pending.join("waiting.pdf.proposal.yaml").exists());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_complete_approved_keeps_validation() {
+        let tmp = tempdir::TempDir::new("mrdocument-test").unwrap();
+        let mut profile = Profile::default();
+        profile.paths = profile.paths.with_path(tmp.path().to_path_buf());
+        let pending = profile.paths.make_root(Location::Pending);
+        fs::create_dir_all(&pending).await.unwrap();
+        let file_path = pending.join("scan.png");
+        fs::write(&file_path, b"\x89PNG\r\n\x1a\n").await.unwrap();
+        let document_data = DocumentData {
+            summary: "Foo".to_string(),
+            class: "invoice".to_string(),
+            source: "stadtwerke".to_string(),
+            keywords: vec!["electricity".to_string()],
+            title: "electricity-march".to_string(),
+            date: "2024-03-01".to_string(),
+            validation: ValidationOutcome {
+                rounds: 1,
+                repaired: vec!["`date` is invalid".to_string()],
+                date_fallback: false,
+            },
+            ..DocumentData::default()
+        };
+        let proposal_path = propose(&file_path, &document_data, None).await.unwrap();
+        let proposal = fs::read_to_string(&proposal_path).await.unwrap();
+        fs::write(
+            &proposal_path,
+            proposal.replace("approved: false", "approved: true"),
+        )
+        .await
+        .unwrap();
+
+        complete_approved(&profile).await.unwrap();
+        assert!(!make_path(&file_path, VALIDATION_SUFFIX).exists());
+        let metadata = fs::read_to_string(
+            profile
+                .paths
+                .make_root(Location::Outbox)
+                .join("invoice/stadtwerke/2024-03-01-electricity-march.metadata.json"),
+        )
+        .await
+        .unwrap();
+        assert!(metadata.contains("passed after 1 repair round(s), fixed: `date` is invalid"));
+    }
+}
@@ -1,16 +1,41 @@
-use env_logger;
+use clap::{Parser, Subcommand};
 use env_logger::{Builder, Env};
+use mrdocument::alias::{merge_dir, plan_renames, propose_merges, scan_outbox};
 use mrdocument::error::{Error, Result};
 use mrdocument::main_loop::run_main_loop;
-use mrdocument::profile::Profile;
+use mrdocument::profile::{AliasProfile, Profile};
 use which::which;
 
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Propose aliases for similar class and source directories in the outbox
+    /// and rename directories according to the aliases of the profile
+    Aliases {
+        /// Name of the profile
+        profile: String,
+        /// Move the contents of non-canonical directories
+        #[arg(long)]
+        apply: bool,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let env = Env::default().filter_or("MRDOCUMENT_LOG_LEVEL", "info");
     Builder::from_env(env).init();
 
-    let result = main_log().await;
+    let cli = Cli::parse();
+    let result = match cli.command {
+        None => main_log().await,
+        Some(Command::Aliases { profile, apply }) => aliases(profile, apply).await,
+    };
     if let Err(ref err) = result {
         log::error!("{err}");
     }
@@ -23,8 +48,38 @@ async fn main_log() -> Result<()> {
 
     mrdocument::api_key::init()?;
     which("pdftoppm").map_err(|_| Error::DependencyMissingError("pdftoppm".to_string()))?;
+    if which("pdftotext").is_err() {
+        log::warn!("pdftotext not found, PDF pages are only sent as images");
+    }
     which("pdftk").map_err(|_| Error::DependencyMissingError("pdftk".to_string()))?;
 
     let path = Profile::get_profile_dir()?;
     run_main_loop(path).await
 }
+
+async fn aliases(profile: String, apply: bool) -> Result<()> {
+    let path = Profile::get_profile_dir()?.join(format!("{profile}.yaml"));
+    let profile = Profile::new_from_file(path).await?;
+
+    let (classes, sources) = scan_outbox(&profile).await?;
+    let proposals = AliasProfile {
+        classes: propose_merges(&classes),
+        sources: propose_merges(&sources),
+    };
+    if proposals.classes.is_empty() && proposals.sources.is_empty() {
+        println!("# No similar directories found");
+    } else {
+        println!("# Proposed aliases, review and add to the profile:");
+        print!("{}", serde_yaml::to_string(&proposals)?);
+    }
+
+    let renames = plan_renames(&profile).await?;
+    for (from, to) in renames {
+        println!("# {} -> {}", from.display(), to.display());
+        if apply {
+            merge_dir(&from, &to).await?;
+        }
+    }
+
+    Ok(())
+}
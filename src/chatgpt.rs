@@ -1,128 +1,98 @@
+use crate::analyzer::{DocumentAnalyzer, DocumentContext};
 use crate::api_key;
 use crate::document::DocumentData;
 use crate::error::{Error, Result};
-use crate::file_info::FileInfo;
-use crate::profile::ChatGptProfile;
-use openai_api_rs::v1::api::OpenAIClient;
+use crate::file_info::Page;
+use crate::http;
+use crate::profile::{ChatGptProfile, OpenAiBackend};
+use crate::prompt::{transcription_function, Prompt, Transcription, TRANSCRIPTION_PROMPT};
+use crate::validation::{make_repair_message, ValidationOutcome, Validator};
 use openai_api_rs::v1::chat_completion::{
-    ChatCompletionMessage, ChatCompletionRequest, MessageRole, Tool, ToolChoiceType,
+    ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, MessageRole, Tool,
+    ToolChoiceType,
 };
 use openai_api_rs::v1::chat_completion::{Content, ContentType, ImageUrl, ImageUrlType};
-use tokio::time::{timeout, Duration};
 use serde_json::json;
 
-fn default_tools() -> Vec<Tool> {
+const API_URL: &str = "https://api.openai.com/v1";
+
+fn default_tools(prompt: &Prompt) -> Vec<Tool> {
     vec![serde_json::from_value(json!({
         "type": "function",
-        "function": {
-            "name": "return_document_data",
-            "description": "Please use this function to return the transcribed content \
-                of the document, your summary of the content, \
-                your classification of the document, the source of the document, \
-                the keywords you assigned, \
-                the title you assigned and the date you determined.",
-            "parameters": {
-                "type": "object",
-                "properties": {
-                    "content": {
-                        "type": "string",
-                        "description": "The contents of the document"
-                    },
-                    "summary": {
-                        "type": "string",
-                        "description": "Your summary of the content"
-                    },
-                    "class": {
-                        "type": "string",
-                        "description": "The class you assigned to the document"
-                    },
-                    "source": {
-                        "type": "string",
-                        "description": "The source you assigned to the document"
-                    },
-                    "keywords": {
-                        "type": "array",
-                        "items": { "type": "string" },
-                        "description": "The keywords assigned to the document"
-                    },
-                    "title": {
-                        "type": "string",
-                        "description": "The title assigned to the document"
-                    },
-                    "date": {
-                        "type": "string",
-                        "description": "The date assigned to the document in YYYY-MM-DD"
-                    }
-                },
-                "required": [
-                    "summary",
-                    "class",
-                    "keywords",
-                    "title",
-                    "date"
-                ]
-            }
-        }
+        "function": prompt.document_data_function(),
     }))
     .unwrap()]
 }
 
-fn make_outputs() -> Vec<String> {
-    vec![
-        "* A transcription of the contents of the document. If the document is too large to provide a full transcription, you may omit this.".to_string(),
-         "* A summary of the content of the entire document.".to_string(),
-         "* A classification of the document. Please use rather broad and general concepts as classes. The class must be usable as part of a filename and must not contain whitespaces or non-ascii characters. Please favor using hyphens over underscores as separators. The grammatical number of the word used as class should be singular if possible.".to_string(),
-        "* The source of the document. This could be the author, creator, sender or issuer of the document. The source must be usable as part of a filename and must not contain whitespaces or non-ascii characters.".to_string(),
-        "* Between 2 and 4 keywords describing the content of the document.".to_string(),
-        "* A title describing the document. It should be sufficiently specific to differentiate this particular document from other documents of this class and source, but it should not duplicate words that are already found as class or source. The title must be usable as part of a filename and must not contain whitespaces or non-ascii characters.".to_string(),
-        "* A date to be associated with the document. Please favor the date when the document was issued over any other dates found.".to_string(),
-    ]
+fn make_instructions(
+    prompt: &Prompt,
+    context: &DocumentContext,
+) -> Result<Vec<ChatCompletionMessage>> {
+    Ok(vec![serde_json::from_value(json!({
+        "role": "system",
+        "content": prompt.make_system_prompt(context)?,
+    }))
+    .unwrap()])
 }
 
-fn make_specs(classes: Vec<String>, sources: Vec<String>) -> Vec<String> {
-    let mut result = vec![
-        "* Please make sure that the language of all outputs matches the language of the input document.".to_string(),
-    ];
-    if classes.len() > 0 {
-        result.push(format!("* When choosing the class of the document, check if any of these classes match before creating a new one: {}", classes.join(", ")));
-    }
-    if sources.len() > 0 {
-        result.push(format!("* When choosing the source of the document, check if any of these sources match before creating a new one: {}", sources.join(", ")));
+fn make_content(page: Page) -> Content {
+    match page {
+        Page::Text(text) => Content::Text(text),
+        Page::Image { mime_type, data } => Content::ImageUrl(vec![ImageUrl {
+            r#type: ContentType::image_url,
+            text: None,
+            image_url: Some(ImageUrlType {
+                url: format!("data:{mime_type};base64,{data}"),
+            }),
+        }]),
     }
+}
 
-    result
+/// The client library drops the headers of failed responses, so requests are
+/// sent directly to keep `Retry-After`.
+async fn send(
+    backend: &OpenAiBackend,
+    req: &ChatCompletionRequest,
+) -> Result<ChatCompletionResponse> {
+    let api_key = if backend.no_api_key {
+        None
+    } else {
+        Some(api_key::get()?.as_str())
+    };
+    post(backend.api_base.as_deref().unwrap_or(API_URL), api_key, req).await
 }
 
-fn make_instructions(classes: Vec<String>, sources: Vec<String>) -> Vec<ChatCompletionMessage> {
-    let outputs = make_outputs().join("\n");
-    let specs = make_specs(classes, sources).join("\n");
-    vec![serde_json::from_value(json!({
-        "role": "system",
-        "content": format!("You will be given a scan of a document. It may consist of one or more pages. You shall provide as output in the language of the document:\n{outputs}\n\nWhen producing the output, you shall observe the following points:\n{specs}\n"),
-    })).unwrap()]
+async fn post(
+    api_base: &str,
+    api_key: Option<&str>,
+    req: &ChatCompletionRequest,
+) -> Result<ChatCompletionResponse> {
+    let mut request = reqwest::Client::new()
+        .post(format!(
+            "{}/chat/completions",
+            api_base.trim_end_matches('/')
+        ))
+        .json(req);
+    if let Some(api_key) = api_key {
+        request = request.bearer_auth(api_key);
+    }
+
+    Ok(serde_json::from_str(&http::send(request).await?)?)
 }
 
 pub async fn query_ai(
     profile: ChatGptProfile,
-    file_info: FileInfo,
-    classes: Vec<String>,
-    sources: Vec<String>,
+    backend: &OpenAiBackend,
+    prompt: &Prompt,
+    validator: &Validator,
+    pages: Vec<Page>,
+    context: DocumentContext,
 ) -> Result<DocumentData> {
-    log::info!("Received {file_info:?}");
-    let api_key = api_key::get();
-    let client = OpenAIClient::builder()
-        .with_api_key(api_key)
-        .build()
-        .map_err(|_| Error::NoApiKeyError)?;
-    let files: Vec<String> = file_info
-        .base64()
-        .await?
-        .into_iter()
-        .map(|data| format!("data:{};base64,{}", file_info.mime_type(), data))
-        .collect();
-
-    let tools = default_tools();
-    let mut messages = make_instructions(classes, sources);
+    log::info!("Received {} pages", pages.len());
+
+    let tools = default_tools(prompt);
+    let temperature = profile.api_temperature();
+    let mut messages = make_instructions(prompt, &context)?;
     for instr in profile.additional_instructions {
         messages.push(ChatCompletionMessage {
             role: MessageRole::system,
@@ -133,52 +103,202 @@ pub async fn query_ai(
         });
     }
     log::debug!("Using instructions: {messages:?}");
-    for file in files {
+    if let Some(hints) = prompt.make_hints(&context) {
+        messages.push(ChatCompletionMessage {
+            role: MessageRole::user,
+            content: Content::Text(hints),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+    }
+    for page in pages {
+        messages.push(ChatCompletionMessage {
+            role: MessageRole::user,
+            content: make_content(page),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+    }
+    let mut repaired = Vec::new();
+    let mut round = 0;
+    loop {
+        log::info!("Sending request to {}", profile.model);
+        let req = ChatCompletionRequest::new(profile.model.clone(), messages.clone())
+            .temperature(temperature)
+            .tools(tools.clone())
+            .tool_choice(ToolChoiceType::Required);
+        let response = send(backend, &req).await?;
+        log::trace!("received response");
+        let Some(tool_call) = response
+            .choices
+            .first()
+            .and_then(|choice| choice.message.tool_calls.as_ref())
+            .and_then(|tool_calls| tool_calls.first())
+            .cloned()
+        else {
+            return Err(Error::DoesNotProcessError(Some(Box::new(response))));
+        };
+        let Some(ref arguments) = tool_call.function.arguments else {
+            return Err(Error::DoesNotProcessError(Some(Box::new(response))));
+        };
+
+        let errors = match serde_json::from_str(arguments)
+            .map_err(|e| vec![format!("The arguments are not valid JSON: {e}")])
+            .and_then(|value| validator.validate(value, &context))
+        {
+            Ok(mut document_data) => {
+                document_data.validation = ValidationOutcome {
+                    rounds: round,
+                    repaired,
+                    ..document_data.validation
+                };
+                return Ok(document_data);
+            }
+            Err(errors) => errors,
+        };
+        if round >= validator.repair_rounds() {
+            return Err(Error::ValidationError(errors));
+        }
+
+        log::warn!(
+            "Invalid response, requesting repair ({}/{}): {}",
+            round + 1,
+            validator.repair_rounds(),
+            errors.join("; ")
+        );
+        messages.push(ChatCompletionMessage {
+            role: MessageRole::assistant,
+            content: Content::Text(String::new()),
+            name: None,
+            tool_calls: Some(vec![tool_call.clone()]),
+            tool_call_id: None,
+        });
+        messages.push(ChatCompletionMessage {
+            role: MessageRole::tool,
+            content: Content::Text(make_repair_message(&errors)),
+            name: None,
+            tool_calls: None,
+            tool_call_id: Some(tool_call.id),
+        });
+        repaired.extend(errors);
+        round += 1;
+    }
+}
+
+/// Requests a plain transcription of `pages`, without any classification.
+pub async fn query_transcription(
+    profile: &ChatGptProfile,
+    backend: &OpenAiBackend,
+    pages: Vec<Page>,
+) -> Result<String> {
+    log::info!("Received {} pages for transcription", pages.len());
+
+    let tools = vec![serde_json::from_value(json!({
+        "type": "function",
+        "function": transcription_function(),
+    }))
+    .unwrap()];
+    let mut messages = vec![ChatCompletionMessage {
+        role: MessageRole::system,
+        content: Content::Text(TRANSCRIPTION_PROMPT.to_string()),
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+    }];
+    for page in pages {
         messages.push(ChatCompletionMessage {
             role: MessageRole::user,
-            content: Content::ImageUrl(vec![ImageUrl {
-                r#type: ContentType::image_url,
-                text: None,
-                image_url: Some(ImageUrlType { url: file }),
-            }]),
+            content: make_content(page),
             name: None,
             tool_calls: None,
             tool_call_id: None,
         });
     }
-    let req = ChatCompletionRequest::new(profile.model, messages)
-        .temperature(<u8 as Into<f64>>::into(profile.temperature) / 100.0)
+
+    log::info!("Sending transcription request to {}", profile.model);
+    let req = ChatCompletionRequest::new(profile.model.clone(), messages)
+        .temperature(profile.api_temperature())
         .tools(tools)
         .tool_choice(ToolChoiceType::Required);
-    log::info!("Sending {file_info:?}");
-    let response = timeout(Duration::from_secs(300), client.chat_completion(req)).await??;
-    log::trace!("received response");
-    let result_value: Result<serde_json::Value> = (|| {
-        Ok(serde_json::from_str(
-            &response
-                .choices
-                .first()
-                .ok_or_else(|| Error::DoesNotProcessError(None))?
-                .message
-                .tool_calls
-                .as_ref()
-                .ok_or_else(|| Error::DoesNotProcessError(None))?
-                .first()
-                .ok_or_else(|| Error::DoesNotProcessError(None))?
-                .function
-                .arguments
-                .as_ref()
-                .ok_or_else(|| Error::DoesNotProcessError(None))?,
-        )?)
-    })()
-    .map_err(|err| {
-        if let Error::DoesNotProcessError(_) = err {
-            Error::DoesNotProcessError(Some(response))
-        } else {
-            err
+    let response = send(backend, &req).await?;
+    let Some(arguments) = response
+        .choices
+        .first()
+        .and_then(|choice| choice.message.tool_calls.as_ref())
+        .and_then(|tool_calls| tool_calls.first())
+        .and_then(|tool_call| tool_call.function.arguments.clone())
+    else {
+        return Err(Error::DoesNotProcessError(Some(Box::new(response))));
+    };
+    let transcription: Transcription = serde_json::from_str(&arguments)?;
+
+    Ok(transcription.content)
+}
+
+pub struct ChatGptAnalyzer {
+    profile: ChatGptProfile,
+    backend: OpenAiBackend,
+    prompt: Prompt,
+    validator: Validator,
+}
+
+impl ChatGptAnalyzer {
+    pub fn new(
+        profile: ChatGptProfile,
+        backend: OpenAiBackend,
+        prompt: Prompt,
+        validator: Validator,
+    ) -> Self {
+        ChatGptAnalyzer {
+            profile,
+            backend,
+            prompt,
+            validator,
         }
-    });
-    let result: Result<DocumentData> = Ok(serde_json::from_value(result_value?)?);
+    }
+}
+
+impl DocumentAnalyzer for ChatGptAnalyzer {
+    async fn analyze(&self, pages: Vec<Page>, context: DocumentContext) -> Result<DocumentData> {
+        query_ai(
+            self.profile.clone(),
+            &self.backend,
+            &self.prompt,
+            &self.validator,
+            pages,
+            context,
+        )
+        .await
+    }
 
-    result
+    async fn transcribe(&self, pages: Vec<Page>) -> Result<String> {
+        query_transcription(&self.profile, &self.backend, pages).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::serve_once;
+    use rstest::rstest;
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let api_base = serve_once(
+            "HTTP/1.1 429 Too Many Requests
+Retry-After: 7",
+            r#"{"error": {"message": "Rate limit hit"}}"#,
+        )
+        .await;
+        let req = ChatCompletionRequest::new("gpt-4o".to_string(), Vec::new());
+        match post(&api_base, None, &req).await {
+            Err(err @ Error::RateLimitError(..)) => {
+                assert_eq!(err.retry_after(), Some(std::time::Duration::from_secs(7)));
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
 }
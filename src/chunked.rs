@@ -0,0 +1,90 @@
+use crate::analyzer::{Analyzer, DocumentAnalyzer, DocumentContext};
+use crate::document::DocumentData;
+use crate::error::Result;
+use crate::file_info::Page;
+use crate::profile::{ChunkingProfile, RetryProfile};
+use crate::retry::backoff_delay;
+use tokio::time::sleep;
+
+pub struct ChunkedAnalyzer {
+    chunking: ChunkingProfile,
+    retry: RetryProfile,
+    analyzer: Box<Analyzer>,
+}
+
+impl ChunkedAnalyzer {
+    pub fn new(chunking: ChunkingProfile, retry: RetryProfile, analyzer: Analyzer) -> Self {
+        ChunkedAnalyzer {
+            chunking,
+            retry,
+            analyzer: Box::new(analyzer),
+        }
+    }
+
+    /// Transient errors have already been retried by the inner analyzer and
+    /// permanent ones would only repeat, only other failures of a batch are retried here.
+    async fn transcribe_batch(&self, batch: Vec<Page>) -> Result<String> {
+        let mut attempt = 0;
+        loop {
+            match self.analyzer.transcribe_boxed(batch.clone()).await {
+                Ok(text) => break Ok(text),
+                Err(err)
+                    if !err.is_transient()
+                        && !err.is_permanent()
+                        && attempt < self.chunking.retries =>
+                {
+                    attempt += 1;
+                    let delay = backoff_delay(&self.retry, attempt);
+                    log::warn!(
+                        "Transcription of batch failed, retrying in {delay:?} ({attempt}/{}): {err}",
+                        self.chunking.retries
+                    );
+                    sleep(delay).await;
+                }
+                Err(err) => break Err(err),
+            }
+        }
+    }
+}
+
+impl DocumentAnalyzer for ChunkedAnalyzer {
+    async fn analyze(&self, pages: Vec<Page>, context: DocumentContext) -> Result<DocumentData> {
+        if pages.len() <= self.chunking.batch_size {
+            return self.analyzer.analyze_boxed(pages, context).await;
+        }
+
+        let content = self.transcribe(pages).await?;
+        log::info!("Classifying transcription");
+        let mut document_data = self
+            .analyzer
+            .analyze_boxed(vec![Page::Text(content.clone())], context)
+            .await?;
+        document_data.content = Some(content);
+
+        Ok(document_data)
+    }
+
+    async fn transcribe(&self, pages: Vec<Page>) -> Result<String> {
+        let batches: Vec<_> = pages
+            .chunks(self.chunking.batch_size.max(1))
+            .map(|batch| batch.to_vec())
+            .collect();
+        let count = batches.len();
+        let mut texts = Vec::new();
+        for (i, batch) in batches.into_iter().enumerate() {
+            if batch.iter().all(|page| matches!(page, Page::Text(_))) {
+                log::debug!("Batch {}/{count} is already text", i + 1);
+                texts.extend(batch.into_iter().filter_map(|page| match page {
+                    Page::Text(text) => Some(text),
+                    _ => None,
+                }));
+                continue;
+            }
+            log::info!("Transcribing batch {}/{count}", i + 1);
+            texts.push(self.transcribe_batch(batch).await?);
+            log::info!("Transcribed batch {}/{count}", i + 1);
+        }
+
+        Ok(texts.join("\n\n"))
+    }
+}
@@ -1,28 +1,82 @@
+use crate::error::Result;
+use crate::profile::LayoutProfile;
+use crate::sanitize::{sanitize_component, sanitize_path, MAX_COMPONENT_BYTES};
+use crate::template::render;
+use crate::util::UNIQUE_SUFFIX_BYTES;
+use crate::validation::ValidationOutcome;
 use display_json::DisplayAsJsonPretty;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
-#[derive(Serialize, Deserialize, DisplayAsJsonPretty)]
+/// Room reserved after the stem for the suffix of each file of a document, so that
+/// all of them share the same stem. The longest is `<language>.summary`.
+pub const MAX_SUFFIX_BYTES: usize = 32;
+
+/// The values the model is asked to rate its confidence in.
+pub const CONFIDENCE_FIELDS: &[&str] = &["class", "source", "keywords", "title", "date"];
+
+#[derive(Default, Serialize, Deserialize, DisplayAsJsonPretty)]
 pub struct DocumentData {
     pub content: Option<String>,
     pub summary: String,
+    #[serde(default)]
+    pub summary_translation: Option<String>,
     pub class: String,
     pub source: String,
     pub keywords: Vec<String>,
     pub title: String,
     pub date: String,
+    #[serde(default)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+    /// Kept as reported by the model, the range is checked by the validator.
+    #[serde(default)]
+    pub confidence: BTreeMap<String, f64>,
+    /// The ID of the e-mail the document is or was attached to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Never read back, so that it cannot be taken from a response.
+    /// Held back documents restore it through `Validator::revalidate`.
+    #[serde(skip_deserializing)]
+    pub validation: ValidationOutcome,
 }
 
 impl DocumentData {
-    pub fn make_filename(&self, suffix: &str) -> String {
-        format!("{}-{}.{}", self.date, self.title, suffix)
+    pub fn make_filename(&self, layout: &LayoutProfile, suffix: &str) -> Result<String> {
+        let max_bytes = MAX_COMPONENT_BYTES - UNIQUE_SUFFIX_BYTES - MAX_SUFFIX_BYTES - 1;
+        Ok(format!(
+            "{}.{}",
+            sanitize_component(&render(&layout.filename, self)?, max_bytes),
+            suffix
+        ))
     }
 
-    pub fn make_path(&self) -> PathBuf {
-        PathBuf::from(format!(
-            "{}/{}",
-            self.class.to_lowercase(),
-            self.source.to_lowercase()
-        ))
+    pub fn make_path(&self, layout: &LayoutProfile) -> Result<PathBuf> {
+        Ok(sanitize_path(&render(&layout.path, self)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    fn test_make_filename_same_stem() {
+        let document_data = DocumentData {
+            title: "electricity-".repeat(30),
+            date: "2024-03-01".to_string(),
+            ..DocumentData::default()
+        };
+        let layout = LayoutProfile::default();
+        let stems: Vec<_> = ["pdf", "docx", "content", "metadata.json", "german.summary"]
+            .into_iter()
+            .map(|suffix| {
+                let filename = document_data.make_filename(&layout, suffix).unwrap();
+                assert!(filename.len() + UNIQUE_SUFFIX_BYTES <= MAX_COMPONENT_BYTES);
+                filename.strip_suffix(suffix).unwrap().to_string()
+            })
+            .collect();
+        assert!(stems.iter().all(|stem| *stem == stems[0]));
     }
 }
@@ -0,0 +1,386 @@
+use crate::error::{Error, Result};
+use crate::file_type::FileType;
+use mail_parser::{Address, MessageParser, MessagePart, MimeHeaders};
+use sha2::{Digest, Sha256};
+use std::io::{Cursor, Read};
+use std::path::Path;
+use tokio::fs;
+
+/// Root storage entries of Outlook messages are named after their MAPI properties.
+const MSG_PREFIX: &str = "__substg1.0_";
+const MSG_ATTACHMENT_PREFIX: &str = "__attach_version1.0_";
+const PR_SUBJECT: &str = "0037";
+const PR_BODY: &str = "1000";
+const PR_DISPLAY_TO: &str = "0E04";
+const PR_SENDER_NAME: &str = "0C1A";
+const PR_SENDER_EMAIL_ADDRESS: &str = "0C1F";
+const PR_SENDER_SMTP_ADDRESS: &str = "5D01";
+const PR_INTERNET_MESSAGE_ID: &str = "1035";
+const PR_ATTACH_DATA_BIN: &str = "3701";
+const PR_ATTACH_FILENAME: &str = "3704";
+const PR_ATTACH_LONG_FILENAME: &str = "3707";
+
+/// What is known about a document from the e-mail it arrived with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Origin {
+    /// The ID of the e-mail the document was attached to, see [`Email::id`].
+    pub email: Option<String>,
+    /// The address the e-mail was sent from.
+    pub sender: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// An e-mail reduced to what is needed to process it: its headers and body as text
+/// and the attachments that are documents of their own.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Email {
+    /// The Message-ID, or a hash of the e-mail if it has none. It stays the same
+    /// when the e-mail is processed again, so documents can be linked by it.
+    pub id: String,
+    pub sender: Option<String>,
+    pub text: String,
+    pub attachments: Vec<Attachment>,
+}
+
+fn format_address(address: &Address) -> String {
+    address
+        .iter()
+        .map(|addr| match (addr.name(), addr.address()) {
+            (Some(name), Some(address)) => format!("{name} <{address}>"),
+            (name, address) => name.or(address).unwrap_or_default().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parts with a content ID are usually images referenced from the HTML body,
+/// e.g. logos in signatures, unless they are explicitly attachments.
+fn is_document(part: &MessagePart) -> bool {
+    part.content_disposition()
+        .is_some_and(|disposition| disposition.is_attachment())
+        || part.content_id().is_none()
+}
+
+fn make_id(message_id: Option<&str>, data: &[u8]) -> String {
+    match message_id.map(|id| id.trim_matches(['<', '>', ' '])) {
+        Some(id) if !id.is_empty() => id.to_string(),
+        _ => format!("sha256:{:x}", Sha256::digest(data)),
+    }
+}
+
+fn make_text(mut headers: Vec<String>, attachments: &[Attachment], body: &[&str]) -> String {
+    if !attachments.is_empty() {
+        let names: Vec<_> = attachments.iter().map(|a| a.name.as_str()).collect();
+        headers.push(format!("Attachments: {}", names.join(", ")));
+    }
+
+    format!("{}\n\n{}", headers.join("\n"), body.join("\n\n"))
+}
+
+/// An Outlook message, which is a compound file with a stream per property.
+struct Msg<'a> {
+    file: cfb::CompoundFile<Cursor<&'a [u8]>>,
+}
+
+impl Msg<'_> {
+    fn read(&mut self, path: &str) -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        self.file
+            .open_stream(path)
+            .ok()?
+            .read_to_end(&mut data)
+            .ok()?;
+        Some(data)
+    }
+
+    /// Reads the string property `id` of the storage `dir`, which is stored
+    /// either as UTF-16 or in the code page of the message.
+    fn string(&mut self, dir: &str, id: &str) -> Option<String> {
+        if let Some(data) = self.read(&format!("{dir}/{MSG_PREFIX}{id}001F")) {
+            let units: Vec<_> = data
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                .collect();
+            return Some(
+                String::from_utf16_lossy(&units)
+                    .trim_end_matches('\0')
+                    .to_string(),
+            );
+        }
+        self.read(&format!("{dir}/{MSG_PREFIX}{id}001E"))
+            .map(|data| {
+                String::from_utf8_lossy(&data)
+                    .trim_end_matches('\0')
+                    .to_string()
+            })
+            .filter(|value| !value.is_empty())
+    }
+
+    fn attachments(&mut self) -> Vec<Attachment> {
+        let dirs: Vec<_> = self
+            .file
+            .read_root_storage()
+            .filter(|entry| entry.is_storage() && entry.name().starts_with(MSG_ATTACHMENT_PREFIX))
+            .map(|entry| entry.path().to_string_lossy().into_owned())
+            .collect();
+        dirs.into_iter()
+            .filter_map(|dir| {
+                let name = self
+                    .string(&dir, PR_ATTACH_LONG_FILENAME)
+                    .or_else(|| self.string(&dir, PR_ATTACH_FILENAME))?;
+                // Embedded messages are stored as storages, not as binary data.
+                let data = self.read(&format!("{dir}/{MSG_PREFIX}{PR_ATTACH_DATA_BIN}0102"))?;
+                Some(Attachment { name, data })
+            })
+            .collect()
+    }
+}
+
+impl Email {
+    /// Parses an e-mail in MIME format or an Outlook message.
+    pub fn parse(data: &[u8]) -> Result<Email> {
+        match FileType::detect(data) {
+            FileType::Ole => Email::parse_msg(data),
+            _ => Email::parse_mime(data),
+        }
+    }
+
+    fn parse_mime(data: &[u8]) -> Result<Email> {
+        let message = MessageParser::default()
+            .parse(data)
+            .ok_or(Error::NotValidEmailError)?;
+        let sender = message
+            .from()
+            .or_else(|| message.sender())
+            .and_then(|address| address.first())
+            .and_then(|addr| addr.address())
+            .map(|address| address.to_lowercase());
+        let attachments: Vec<_> = message
+            .attachments()
+            .filter(|part| is_document(part))
+            .filter_map(|part| {
+                let name = part
+                    .attachment_name()
+                    .map(str::to_string)
+                    .or_else(|| part.is_message().then(|| "message.eml".to_string()))?;
+                Some(Attachment {
+                    name,
+                    data: part.contents().to_vec(),
+                })
+            })
+            .collect();
+
+        let mut headers = Vec::new();
+        for (name, address) in [("From", message.from()), ("To", message.to())] {
+            if let Some(address) = address {
+                headers.push(format!("{name}: {}", format_address(address)));
+            }
+        }
+        if let Some(date) = message.date() {
+            headers.push(format!("Date: {}", date.to_rfc3339()));
+        }
+        if let Some(subject) = message.subject() {
+            headers.push(format!("Subject: {subject}"));
+        }
+        let body: Vec<_> = (0..message.text_body_count())
+            .filter_map(|pos| message.body_text(pos))
+            .collect();
+        let body: Vec<_> = body.iter().map(|text| text.as_ref()).collect();
+
+        Ok(Email {
+            id: make_id(message.message_id(), data),
+            sender,
+            text: make_text(headers, &attachments, &body),
+            attachments,
+        })
+    }
+
+    fn parse_msg(data: &[u8]) -> Result<Email> {
+        let file =
+            cfb::CompoundFile::open(Cursor::new(data)).map_err(|_| Error::NotValidEmailError)?;
+        let mut msg = Msg { file };
+        let root = "";
+        let subject = msg.string(root, PR_SUBJECT);
+        let body = msg.string(root, PR_BODY);
+        if subject.is_none() && body.is_none() {
+            return Err(Error::NotValidEmailError);
+        }
+        // Senders within Exchange have an X.500 address, not an SMTP address.
+        let sender = msg
+            .string(root, PR_SENDER_SMTP_ADDRESS)
+            .or_else(|| msg.string(root, PR_SENDER_EMAIL_ADDRESS))
+            .filter(|address| address.contains('@'))
+            .map(|address| address.to_lowercase());
+        let attachments = msg.attachments();
+
+        let mut headers = Vec::new();
+        match (msg.string(root, PR_SENDER_NAME), &sender) {
+            (Some(name), Some(address)) => headers.push(format!("From: {name} <{address}>")),
+            (name, address) => {
+                if let Some(from) = name.or(address.clone()) {
+                    headers.push(format!("From: {from}"));
+                }
+            }
+        }
+        if let Some(to) = msg.string(root, PR_DISPLAY_TO) {
+            headers.push(format!("To: {to}"));
+        }
+        if let Some(subject) = subject {
+            headers.push(format!("Subject: {subject}"));
+        }
+        let body = body.unwrap_or_default();
+
+        Ok(Email {
+            id: make_id(msg.string(root, PR_INTERNET_MESSAGE_ID).as_deref(), data),
+            sender,
+            text: make_text(headers, &attachments, &[&body]),
+            attachments,
+        })
+    }
+
+    pub async fn read(path: &Path) -> Result<Email> {
+        Email::parse(&fs::read(path).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const EMAIL: &str = "From: Stadtwerke <Billing@Stadtwerke.example>\r
+To: customer@example.com\r
+Subject: Your invoice\r
+Date: Fri, 1 Mar 2024 10:00:00 +0100\r
+Message-ID: <invoice-117@stadtwerke.example>\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"outer\"\r
+\r
+--outer\r
+Content-Type: multipart/related; boundary=\"inner\"\r
+\r
+--inner\r
+Content-Type: text/html; charset=utf-8\r
+\r
+<p>Please find your invoice attached.</p><img src=\"cid:logo\">\r
+--inner\r
+Content-Type: image/png; name=\"logo.png\"\r
+Content-ID: <logo>\r
+Content-Transfer-Encoding: base64\r
+\r
+iVBORw0KGgo=\r
+--inner--\r
+--outer\r
+Content-Type: application/pdf; name=\"invoice.pdf\"\r
+Content-Disposition: attachment; filename=\"invoice.pdf\"\r
+Content-Transfer-Encoding: base64\r
+\r
+JVBERi0xLjcK\r
+--outer--\r
+";
+
+    #[rstest]
+    fn test_parse() {
+        let email = Email::parse(EMAIL.as_bytes()).unwrap();
+        assert_eq!(email.id, "invoice-117@stadtwerke.example");
+        assert_eq!(email.sender.as_deref(), Some("billing@stadtwerke.example"));
+        assert_eq!(
+            email.attachments,
+            vec![Attachment {
+                name: "invoice.pdf".to_string(),
+                data: b"%PDF-1.7\n".to_vec(),
+            }]
+        );
+        assert!(email.text.starts_with(
+            "From: Stadtwerke <Billing@Stadtwerke.example>\nTo: customer@example.com\n"
+        ));
+        assert!(email.text.contains("Subject: Your invoice\n"));
+        assert!(email.text.contains("Attachments: invoice.pdf\n"));
+        assert!(email.text.contains("Please find your invoice attached."));
+    }
+
+    #[rstest]
+    fn test_parse_without_message_id() {
+        let data = b"From: billing@stadtwerke.example\r\nSubject: Reminder\r\n\r\nPlease pay.\r\n";
+        let email = Email::parse(data).unwrap();
+        assert!(email.id.starts_with("sha256:"));
+        assert_eq!(email.id, Email::parse(data).unwrap().id);
+    }
+
+    fn utf16(value: &str) -> Vec<u8> {
+        value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn make_msg(streams: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut file = cfb::CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        for (path, data) in streams {
+            if let Some((dir, _)) = path.rsplit_once('/').filter(|(dir, _)| !dir.is_empty()) {
+                if !file.exists(dir) {
+                    file.create_storage(dir).unwrap();
+                }
+            }
+            std::io::Write::write_all(&mut file.create_stream(path).unwrap(), data).unwrap();
+        }
+        file.into_inner().into_inner()
+    }
+
+    #[rstest]
+    fn test_parse_msg() {
+        let data = make_msg(&[
+            ("/__substg1.0_0037001F", utf16("Your invoice")),
+            (
+                "/__substg1.0_1000001F",
+                utf16("Please find your invoice attached."),
+            ),
+            ("/__substg1.0_0C1A001F", utf16("Stadtwerke")),
+            ("/__substg1.0_0C1F001F", utf16("/O=EXCHANGE/CN=BILLING")),
+            ("/__substg1.0_5D01001F", utf16("Billing@Stadtwerke.example")),
+            ("/__substg1.0_0E04001F", utf16("Customer")),
+            (
+                "/__substg1.0_1035001F",
+                utf16("<invoice-117@stadtwerke.example>"),
+            ),
+            (
+                "/__attach_version1.0_#00000000/__substg1.0_3707001F",
+                utf16("invoice.pdf"),
+            ),
+            (
+                "/__attach_version1.0_#00000000/__substg1.0_37010102",
+                b"%PDF-1.7\n".to_vec(),
+            ),
+        ]);
+        let email = Email::parse(&data).unwrap();
+        assert_eq!(email.id, "invoice-117@stadtwerke.example");
+        assert_eq!(email.sender.as_deref(), Some("billing@stadtwerke.example"));
+        assert_eq!(
+            email.attachments,
+            vec![Attachment {
+                name: "invoice.pdf".to_string(),
+                data: b"%PDF-1.7\n".to_vec(),
+            }]
+        );
+        assert_eq!(
+            email.text,
+            "From: Stadtwerke <billing@stadtwerke.example>\nTo: Customer\nSubject: Your invoice\nAttachments: invoice.pdf\n\nPlease find your invoice attached."
+        );
+    }
+
+    #[rstest]
+    fn test_parse_msg_without_message() {
+        let data = make_msg(&[("/WordDocument", b"\0\0".to_vec())]);
+        assert!(matches!(
+            Email::parse(&data),
+            Err(Error::NotValidEmailError)
+        ));
+    }
+
+    #[rstest]
+    fn test_parse_invalid() {
+        assert!(matches!(Email::parse(b""), Err(Error::NotValidEmailError)));
+    }
+}
@@ -1,7 +1,9 @@
 use openai_api_rs::v1::chat_completion::ChatCompletionResponse;
 use std::path::PathBuf;
+use std::time::Duration;
+use strum_macros::IntoStaticStr;
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, thiserror::Error, IntoStaticStr)]
 pub enum Error {
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
@@ -15,20 +17,35 @@ pub enum Error {
     #[error("Serde YAML error: {0}")]
     SerdeYamlError(#[from] serde_yaml::Error),
 
+    #[error("Image error: {0}")]
+    ImageError(#[from] image::ImageError),
+
+    #[error("Image conversion error: {0}")]
+    ImageConversionError(String),
+
+    #[error("Office conversion error: {0}")]
+    OfficeConversionError(String),
+
     #[error("Stream closed")]
     StreamClosedError,
 
     #[error("No API key provided")]
     NoApiKeyError,
 
-    #[error("Api error: {0:?}")]
-    ApiError(#[from] openai_api_rs::v1::error::APIError),
+    #[error("HTTP error: {0}")]
+    HttpError(#[from] reqwest::Error),
+
+    #[error("HTTP status {0}: {1}")]
+    HttpStatusError(u16, String),
+
+    #[error("Rate limited: {0}")]
+    RateLimitError(String, Option<Duration>),
 
     #[error("Document cannot be processed: {0:?}")]
-    DoesNotProcessError(Option<ChatCompletionResponse>),
+    DoesNotProcessError(Option<Box<ChatCompletionResponse>>),
 
-    #[error("File type not supported: {0:?}")]
-    UnsupportedFileTypeError(PathBuf),
+    #[error("File type not supported: {1}: {0:?}")]
+    UnsupportedFileTypeError(PathBuf, String),
 
     #[error("Unexpected: {0}")]
     UnexpectedError(String),
@@ -48,12 +65,30 @@ pub enum Error {
     #[error("Not a valid PDF")]
     NotValidPdfError,
 
+    #[error("Not a valid e-mail")]
+    NotValidEmailError,
+
     #[error("File disappeared: {0:?}")]
     FileDisappearedError(PathBuf),
 
+    #[error("No review data found for {0:?}")]
+    MissingReviewDataError(PathBuf),
+
+    #[error("Path escapes its root: {0:?}")]
+    InvalidPathError(PathBuf),
+
+    #[error("Invalid template: {0}")]
+    TemplateError(String),
+
+    #[error("Invalid response: {}", .0.join("; "))]
+    ValidationError(Vec<String>),
+
     #[error("Cannot convert PDF: {0}")]
     PdfConversionError(String),
 
+    #[error("OCR failed: {0}")]
+    OcrError(String),
+
     #[error("Dependency missing: {0}")]
     DependencyMissingError(String),
 
@@ -76,4 +111,51 @@ pub enum Error {
     Other(String),
 }
 
+impl Error {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::HttpError(err) => err.is_timeout() || err.is_connect(),
+            Error::HttpStatusError(status, _) => is_transient_status(*status),
+            Error::RateLimitError(_, _) | Error::TimeoutError(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Errors that occur the same way on every attempt, so that requeueing
+    /// the document would only repeat them.
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            Error::UnsupportedFileTypeError(..)
+                | Error::ValidationError(_)
+                | Error::TemplateError(_)
+                | Error::InvalidPathError(_)
+                | Error::NotValidPdfError
+                | Error::NotValidEmailError
+                | Error::MissingReviewDataError(_)
+                | Error::DoesNotProcessError(_)
+                | Error::ImageError(_)
+                | Error::EncodingError
+                | Error::FileExists(_)
+                | Error::NoApiKeyError
+                | Error::DependencyMissingError(_)
+        ) || matches!(self, Error::HttpStatusError(status, _) if is_permanent_status(*status))
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::RateLimitError(_, retry_after) => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+fn is_transient_status(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500..=599)
+}
+
+fn is_permanent_status(status: u16) -> bool {
+    (400..=499).contains(&status) && !is_transient_status(status)
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
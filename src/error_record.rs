@@ -0,0 +1,185 @@
+use crate::error::{Error, Result};
+use crate::paths::{Location, Paths};
+use crate::profile::RequeueProfile;
+use crate::util::{file_exists, move_file, run_scheduler};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::time::Duration;
+
+const SUFFIX: &str = ".error.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ErrorRecord {
+    pub variant: String,
+    pub message: String,
+    pub retryable: bool,
+    pub attempts: u32,
+    pub first_failed: DateTime<Utc>,
+    pub last_failed: DateTime<Utc>,
+    #[serde(default)]
+    pub requeued: Option<DateTime<Utc>>,
+    /// The file name of the original in the processed location, for documents
+    /// converted to PDF whose original is to be filed alongside.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original: Option<String>,
+}
+
+impl ErrorRecord {
+    pub fn new(err: &Error, previous: Option<ErrorRecord>, original: Option<&str>) -> Self {
+        let now = Utc::now();
+        let previous = previous.filter(|previous| previous.requeued.is_some());
+        ErrorRecord {
+            variant: <&'static str>::from(err).to_string(),
+            message: err.to_string(),
+            retryable: err.is_transient() || !err.is_permanent(),
+            attempts: previous
+                .as_ref()
+                .map_or(1, |previous| previous.attempts + 1),
+            first_failed: previous.map_or(now, |previous| previous.first_failed),
+            last_failed: now,
+            requeued: None,
+            original: original.map(str::to_string),
+        }
+    }
+
+    pub fn make_path(file_path: &Path) -> PathBuf {
+        let mut filename = file_path.file_name().unwrap_or_default().to_os_string();
+        filename.push(SUFFIX);
+        file_path.with_file_name(filename)
+    }
+
+    pub async fn load(file_path: &Path) -> Option<ErrorRecord> {
+        let data = fs::read_to_string(ErrorRecord::make_path(file_path))
+            .await
+            .ok()?;
+        serde_json::from_str(&data)
+            .inspect_err(|e| log::warn!("Ignoring invalid error record for {file_path:?}: {e:?}"))
+            .ok()
+    }
+
+    pub async fn write(&self, file_path: &Path) -> Result<()> {
+        Ok(fs::write(
+            ErrorRecord::make_path(file_path),
+            serde_json::to_string_pretty(self)?,
+        )
+        .await?)
+    }
+
+    pub async fn remove(file_path: &Path) -> Result<()> {
+        let path = ErrorRecord::make_path(file_path);
+        if file_exists(&path) {
+            fs::remove_file(path).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn record(
+        file_path: &Path,
+        err: &Error,
+        original: Option<&str>,
+    ) -> Result<ErrorRecord> {
+        let record = ErrorRecord::new(err, ErrorRecord::load(file_path).await, original);
+        record.write(file_path).await?;
+        Ok(record)
+    }
+
+    fn is_due(&self, requeue: &RequeueProfile, now: DateTime<Utc>) -> bool {
+        self.retryable
+            && self.requeued.is_none()
+            && self.attempts < requeue.max_attempts
+            && now >= self.last_failed + Duration::from_secs(requeue.delay)
+    }
+}
+
+async fn requeue_due_files(paths: &Paths, requeue: &RequeueProfile) -> Result<()> {
+    let error_root = paths.make_root(Location::Error);
+    let mut entries = fs::read_dir(&error_root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let record_filename = entry.file_name().to_string_lossy().into_owned();
+        let Some(filename) = record_filename.strip_suffix(SUFFIX) else {
+            continue;
+        };
+        let file_path = error_root.join(filename);
+        if !file_exists(&file_path) {
+            continue;
+        }
+        let Some(mut record) = ErrorRecord::load(&file_path).await else {
+            continue;
+        };
+        let now = Utc::now();
+        if !record.is_due(requeue, now) {
+            continue;
+        }
+
+        log::info!(
+            "Requeueing {file_path:?} (attempt {}/{})",
+            record.attempts + 1,
+            requeue.max_attempts
+        );
+        record.requeued = Some(now);
+        record.write(&file_path).await?;
+        let inbox_path = paths.make_root(Location::Inbox).join(filename);
+        move_file(&file_path, &inbox_path).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn run_requeue_scheduler(paths: Paths, requeue: RequeueProfile) {
+    run_scheduler(Duration::from_secs(60), "requeue error files", || {
+        requeue_due_files(&paths, &requeue)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn make_record(attempts: u32, requeued: bool) -> ErrorRecord {
+        let now = Utc::now();
+        ErrorRecord {
+            variant: "TimeoutError".to_string(),
+            message: "Timeout".to_string(),
+            retryable: true,
+            attempts,
+            first_failed: now,
+            last_failed: now,
+            requeued: requeued.then_some(now),
+            original: None,
+        }
+    }
+
+    #[rstest]
+    #[case(None, 1)]
+    #[case(Some(make_record(2, true)), 3)]
+    #[case(Some(make_record(2, false)), 1)]
+    fn test_attempts(#[case] previous: Option<ErrorRecord>, #[case] attempts: u32) {
+        let record = ErrorRecord::new(&Error::Other("foo".to_string()), previous, None);
+        assert_eq!(record.attempts, attempts);
+        assert_eq!(record.variant, "Other");
+    }
+
+    #[rstest]
+    #[case(Error::Other("foo".to_string()), true)]
+    #[case(Error::HttpStatusError(503, "Service Unavailable".to_string()), true)]
+    #[case(Error::ValidationError(vec!["`date` must be a valid date".to_string()]), false)]
+    #[case(Error::TemplateError("Unknown variable: titel".to_string()), false)]
+    #[case(Error::NotValidEmailError, false)]
+    #[case(Error::NoApiKeyError, false)]
+    #[case(Error::DependencyMissingError("tesseract".to_string()), false)]
+    fn test_retryable(#[case] err: Error, #[case] retryable: bool) {
+        assert_eq!(ErrorRecord::new(&err, None, None).retryable, retryable);
+    }
+
+    #[rstest]
+    fn test_make_path() {
+        assert_eq!(
+            ErrorRecord::make_path(&PathBuf::from("/home/baz/error/foobar.pdf")),
+            PathBuf::from("/home/baz/error/foobar.pdf.error.json")
+        );
+    }
+}
@@ -1,5 +1,11 @@
+use crate::email::Email;
 use crate::error::{Error, Result};
+use crate::file_type::FileType;
+use crate::office;
+use crate::photo;
+use crate::profile::{ImageFormat, PageProfile};
 use base64::{engine::general_purpose, Engine as _};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::process::Stdio;
 use tempdir::TempDir;
@@ -9,48 +15,337 @@ use tokio::process::Command;
 use tokio_stream::wrappers::LinesStream;
 use tokio_stream::StreamExt;
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum Page {
+    Text(String),
+    Image { mime_type: String, data: String },
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum FileInfo {
     PDF(PathBuf),
     PNG(PathBuf),
     JPEG(PathBuf),
+    TIFF(PathBuf),
+    WEBP(PathBuf),
+    HEIC(PathBuf),
+    OFFICE(PathBuf),
+    EMAIL(PathBuf),
 }
 
 impl FileInfo {
+    /// Determines the file type by extension. The magic bytes are verified by `check`.
     pub fn new(path: PathBuf) -> Result<Self> {
-        let ext = path
-            .extension()
-            .ok_or_else(|| Error::UnsupportedFileTypeError(path.clone()))?;
+        let unsupported = |path: &PathBuf| {
+            Error::UnsupportedFileTypeError(path.clone(), FileType::detect_file(path).to_string())
+        };
+        let ext = path.extension().ok_or_else(|| unsupported(&path))?;
         match ext
             .to_ascii_lowercase()
             .to_str()
-            .ok_or_else(|| Error::UnsupportedFileTypeError(path.clone()))?
+            .ok_or_else(|| unsupported(&path))?
         {
             "pdf" => Ok(FileInfo::PDF(path)),
-            //            "png" => Ok(FileInfo::PNG(path)),
-            //            "jpg" => Ok(FileInfo::JPEG(path)),
-            //            "jpeg" => Ok(FileInfo::JPEG(path)),
-            _ => Err(Error::UnsupportedFileTypeError(path.clone())),
+            "png" => Ok(FileInfo::PNG(path)),
+            "jpg" => Ok(FileInfo::JPEG(path)),
+            "jpeg" => Ok(FileInfo::JPEG(path)),
+            "tif" => Ok(FileInfo::TIFF(path)),
+            "tiff" => Ok(FileInfo::TIFF(path)),
+            "webp" => Ok(FileInfo::WEBP(path)),
+            "heic" => Ok(FileInfo::HEIC(path)),
+            "heif" => Ok(FileInfo::HEIC(path)),
+            "eml" | "msg" => Ok(FileInfo::EMAIL(path)),
+            ext if office::file_type(ext).is_some() => Ok(FileInfo::OFFICE(path)),
+            _ => Err(unsupported(&path)),
+        }
+    }
+
+    fn get_path(&self) -> &PathBuf {
+        match self {
+            FileInfo::PDF(path)
+            | FileInfo::PNG(path)
+            | FileInfo::JPEG(path)
+            | FileInfo::TIFF(path)
+            | FileInfo::WEBP(path)
+            | FileInfo::HEIC(path)
+            | FileInfo::OFFICE(path)
+            | FileInfo::EMAIL(path) => path,
+        }
+    }
+
+    pub fn file_type(&self) -> FileType {
+        match self {
+            FileInfo::PDF(_) => FileType::Pdf,
+            FileInfo::PNG(_) => FileType::Png,
+            FileInfo::JPEG(_) => FileType::Jpeg,
+            FileInfo::TIFF(_) => FileType::Tiff,
+            FileInfo::WEBP(_) => FileType::Webp,
+            FileInfo::HEIC(_) => FileType::Heic,
+            FileInfo::OFFICE(_) => {
+                office::file_type(&self.extension()).unwrap_or(FileType::Unknown)
+            }
+            FileInfo::EMAIL(_) if self.extension() == "msg" => FileType::Ole,
+            FileInfo::EMAIL(_) => FileType::Email,
+        }
+    }
+
+    pub fn is_image(&self) -> bool {
+        !matches!(
+            self,
+            FileInfo::PDF(_) | FileInfo::OFFICE(_) | FileInfo::EMAIL(_)
+        )
+    }
+
+    /// The extension under which the file is stored in the outbox.
+    pub fn extension(&self) -> String {
+        match self {
+            FileInfo::PDF(_) => "pdf",
+            FileInfo::PNG(_) => "png",
+            FileInfo::JPEG(_) => "jpg",
+            FileInfo::TIFF(_) => "tiff",
+            FileInfo::WEBP(_) => "webp",
+            FileInfo::HEIC(_) => "heic",
+            FileInfo::OFFICE(path) | FileInfo::EMAIL(path) => {
+                return path
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_lowercase()
+            }
+        }
+        .to_string()
+    }
+
+    /// Checks that the content matches the extension and that the file is complete.
+    /// Files too short to detect their type are treated as incomplete.
+    pub async fn check(&self) -> Result<()> {
+        let detected = FileType::detect_file(self.get_path());
+        if detected != FileType::Unknown && detected != self.file_type() {
+            return Err(Error::UnsupportedFileTypeError(
+                self.get_path().clone(),
+                format!("{detected}, expected {}", self.file_type()),
+            ));
+        }
+        match self {
+            FileInfo::PDF(path) => lopdf::Document::load(path)
+                .await
+                .map(|_| ())
+                .map_err(|_| Error::NotValidPdfError),
+            FileInfo::OFFICE(path) => {
+                let data = fs::read(path).await?;
+                if detected == FileType::Unknown || !office::is_complete(&data) {
+                    return Err(Error::Other(format!("Incomplete document: {path:?}")));
+                }
+                Ok(())
+            }
+            FileInfo::EMAIL(path) => Email::read(path).await.map(|_| ()),
+            _ => photo::check(self.read_image().await?)
+                .await
+                .map_err(|err| match err {
+                    Error::ImageError(image::ImageError::Unsupported(err)) => {
+                        Error::UnsupportedFileTypeError(
+                            self.get_path().clone(),
+                            format!("{}: {err}", self.file_type()),
+                        )
+                    }
+                    err => err,
+                }),
+        }
+    }
+
+    /// Reads the image, converting HEIC to PNG as it cannot be decoded directly.
+    pub async fn read_image(&self) -> Result<Vec<u8>> {
+        match self {
+            FileInfo::HEIC(path) => photo::convert_heic(path).await,
+            _ => Ok(fs::read(self.get_path()).await?),
+        }
+    }
+
+    /// The format images are sent in. PNG and JPEG are kept, others are converted.
+    fn upload_format(&self, profile: &PageProfile) -> image::ImageFormat {
+        match (self, profile.format) {
+            (FileInfo::PNG(_), _) | (_, ImageFormat::Png) => image::ImageFormat::Png,
+            (FileInfo::JPEG(_), _) | (_, ImageFormat::Jpeg) => image::ImageFormat::Jpeg,
         }
     }
 
-    pub async fn base64(&self) -> Result<Vec<String>> {
+    fn conversion_missing(&self) -> Error {
+        Error::UnsupportedFileTypeError(
+            self.get_path().clone(),
+            format!("{}, office conversion is not configured", self.extension()),
+        )
+    }
+
+    pub async fn base64(&self, profile: &PageProfile) -> Result<Vec<String>> {
         match self {
-            FileInfo::PDF(path) => FileInfo::process_pdf(path).await,
-            FileInfo::PNG(path) => vec![FileInfo::process_image(path).await]
-                .into_iter()
-                .collect(),
-            FileInfo::JPEG(path) => vec![FileInfo::process_image(path).await]
-                .into_iter()
-                .collect(),
+            FileInfo::OFFICE(_) => Err(self.conversion_missing()),
+            FileInfo::EMAIL(path) => Err(Error::UnsupportedFileTypeError(
+                path.clone(),
+                "email, not an image".to_string(),
+            )),
+            FileInfo::PDF(path) => {
+                let page_count = FileInfo::count_pdf_pages(path).await?;
+                Ok(
+                    FileInfo::process_pdf(path, profile, &profile.select_pages(page_count))
+                        .await?
+                        .into_iter()
+                        .map(|(_, data)| data)
+                        .collect(),
+                )
+            }
+            _ => {
+                let data = self.read_image().await?;
+                let frame_count = photo::frame_count(&data)?;
+                let frames = profile.select_pages(frame_count);
+                if frames.len() < frame_count as usize {
+                    log::info!(
+                        "Using {} of {} frames of {:?}",
+                        frames.len(),
+                        frame_count,
+                        self.get_path()
+                    );
+                }
+                photo::process(data, frames, profile, self.upload_format(profile)).await
+            }
         }
     }
 
-    pub fn mime_type(&self) -> String {
+    pub async fn pages(&self, profile: &PageProfile) -> Result<Vec<Page>> {
         match self {
-            FileInfo::PDF(_) => "image/png".to_string(),
-            FileInfo::PNG(_) => "image/png".to_string(),
-            FileInfo::JPEG(_) => "image/jpeg".to_string(),
+            FileInfo::PDF(path) => FileInfo::process_pdf_pages(path, profile).await,
+            FileInfo::OFFICE(_) => Err(self.conversion_missing()),
+            FileInfo::EMAIL(path) => Ok(vec![Page::Text(Email::read(path).await?.text)]),
+            _ => {
+                let mime_type = self.mime_type(profile);
+                Ok(self
+                    .base64(profile)
+                    .await?
+                    .into_iter()
+                    .map(|data| Page::Image {
+                        mime_type: mime_type.clone(),
+                        data,
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    async fn count_pdf_pages(pdf_path: &PathBuf) -> Result<u32> {
+        let document = lopdf::Document::load(pdf_path)
+            .await
+            .map_err(|_| Error::NotValidPdfError)?;
+
+        Ok(document.get_pages().len() as u32)
+    }
+
+    /// The longest side of each page in points, as far as the document specifies it.
+    async fn pdf_page_extents(pdf_path: &PathBuf) -> Result<HashMap<u32, f32>> {
+        let document = lopdf::Document::load(pdf_path)
+            .await
+            .map_err(|_| Error::NotValidPdfError)?;
+
+        Ok(document
+            .get_pages()
+            .into_iter()
+            .filter_map(|(page, id)| Some((page, page_extent(&document, id)?)))
+            .collect())
+    }
+
+    async fn process_pdf_pages(pdf_path: &PathBuf, profile: &PageProfile) -> Result<Vec<Page>> {
+        let page_count = FileInfo::count_pdf_pages(pdf_path).await?;
+        let selected = profile.select_pages(page_count);
+        if selected.len() < page_count as usize {
+            log::info!(
+                "Using {} of {} pages of {:?}",
+                selected.len(),
+                page_count,
+                pdf_path
+            );
+        }
+
+        let mut texts = HashMap::new();
+        if profile.text_layer {
+            match FileInfo::extract_pdf_text(pdf_path).await {
+                Ok(extracted) => {
+                    if let Some(text_pages) =
+                        text_pages(extracted, page_count, &selected, profile.min_text_length)
+                    {
+                        texts = text_pages;
+                    } else {
+                        log::warn!(
+                            "Page count mismatch between text layer and document {:?}, using images only",
+                            pdf_path
+                        );
+                    }
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Unable to extract text layer of {:?}, using images only: {}",
+                        pdf_path,
+                        err
+                    );
+                }
+            }
+            log::info!(
+                "Using text layer for {} of {} pages of {:?}",
+                texts.len(),
+                selected.len(),
+                pdf_path
+            );
+        }
+
+        let to_render: Vec<_> = selected
+            .iter()
+            .filter(|page| !texts.contains_key(page))
+            .cloned()
+            .collect();
+        let mut images: HashMap<_, _> = FileInfo::process_pdf(pdf_path, profile, &to_render)
+            .await?
+            .into_iter()
+            .collect();
+
+        let mime_type = profile.format.mime_type();
+        selected
+            .into_iter()
+            .map(|page| {
+                if let Some(text) = texts.remove(&page) {
+                    Ok(Page::Text(text))
+                } else {
+                    images
+                        .remove(&page)
+                        .map(|data| Page::Image {
+                            mime_type: mime_type.clone(),
+                            data,
+                        })
+                        .ok_or_else(|| {
+                            Error::PdfConversionError(format!("Page {page} was not rendered"))
+                        })
+                }
+            })
+            .collect()
+    }
+
+    async fn extract_pdf_text(pdf_path: &PathBuf) -> Result<Vec<String>> {
+        let output = Command::new("pdftotext")
+            .arg("-enc")
+            .arg("UTF-8")
+            .arg(pdf_path)
+            .arg("-")
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(Error::PdfConversionError(
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            ));
+        }
+
+        Ok(split_pages(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    pub fn mime_type(&self, profile: &PageProfile) -> String {
+        match self {
+            FileInfo::PDF(_) => profile.format.mime_type(),
+            _ => self.upload_format(profile).to_mime_type().to_string(),
         }
     }
 
@@ -59,52 +354,91 @@ impl FileInfo {
         Ok(general_purpose::STANDARD.encode(image_data))
     }
 
-    async fn process_pdf(pdf_path: &PathBuf) -> Result<Vec<String>> {
+    async fn process_pdf(
+        pdf_path: &PathBuf,
+        profile: &PageProfile,
+        pages: &[u32],
+    ) -> Result<Vec<(u32, String)>> {
+        if pages.is_empty() {
+            return Ok(Vec::new());
+        }
         let tmp_dir = TempDir::new("mrdocument")?;
 
         let result = async {
             let pdf_filename = pdf_path
                 .file_name()
                 .ok_or(Error::Other("Invalid PDF file name".to_string()))?;
-            let tmp_pdf_path = tmp_dir.path().join(&pdf_filename);
+            let tmp_pdf_path = tmp_dir.path().join(pdf_filename);
             fs::copy(pdf_path, &tmp_pdf_path).await?;
 
-            let mut output = Command::new("pdftoppm")
-                .arg(&pdf_filename)
-                .arg("image")
-                .arg("-png")
-                .current_dir(&tmp_dir)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()?;
-
-            let stdout = LinesStream::new(
-                BufReader::new(output.stdout.take().ok_or(Error::RedirectIOError)?).lines(),
-            );
-            let stderr = LinesStream::new(
-                BufReader::new(output.stderr.take().ok_or(Error::RedirectIOError)?).lines(),
-            );
-            let out_stream = StreamExt::merge(stdout, stderr);
+            let extents = match profile.max_dimension {
+                Some(_) => FileInfo::pdf_page_extents(pdf_path).await?,
+                None => HashMap::new(),
+            };
+            let resolutions: Vec<_> = pages
+                .iter()
+                .map(|page| {
+                    (
+                        *page,
+                        page_resolution(
+                            extents.get(page).copied(),
+                            profile.dpi,
+                            profile.max_dimension,
+                        ),
+                    )
+                })
+                .collect();
+            for (first, last, dpi) in page_ranges(&resolutions) {
+                let mut command = Command::new("pdftoppm");
+                command
+                    .arg("-f")
+                    .arg(first.to_string())
+                    .arg("-l")
+                    .arg(last.to_string());
+                if let Some(dpi) = dpi {
+                    command.arg("-r").arg(dpi.to_string());
+                }
+                if profile.grayscale {
+                    command.arg("-gray");
+                }
+                let mut output = command
+                    .arg(format!("-{}", profile.format))
+                    .arg(pdf_filename)
+                    .arg("image")
+                    .current_dir(&tmp_dir)
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
 
-            let status = output.wait().await?;
-            if !status.success() {
-                return Err(Error::PdfConversionError(
-                    out_stream
-                        .collect::<std::result::Result<Vec<_>, _>>()
-                        .await?
-                        .join("\n"),
-                ));
+                let stdout = LinesStream::new(
+                    BufReader::new(output.stdout.take().ok_or(Error::RedirectIOError)?).lines(),
+                );
+                let stderr = LinesStream::new(
+                    BufReader::new(output.stderr.take().ok_or(Error::RedirectIOError)?).lines(),
+                );
+                let out_stream = StreamExt::merge(stdout, stderr);
+
+                let status = output.wait().await?;
+                if !status.success() {
+                    return Err(Error::PdfConversionError(
+                        out_stream
+                            .collect::<std::result::Result<Vec<_>, _>>()
+                            .await?
+                            .join("\n"),
+                    ));
+                }
             }
 
             let mut entries = fs::read_dir(&tmp_dir).await?;
             let mut image_files = Vec::new();
+            let suffix = format!(".{}", profile.format.extension());
 
             while let Some(entry) = entries.next_entry().await? {
                 let file_name = entry.file_name();
                 let file_name = file_name.to_string_lossy();
 
-                if file_name.starts_with("image-") && file_name.ends_with(".png") {
-                    let page_str = &file_name["image-".len()..file_name.len() - ".png".len()];
+                if file_name.starts_with("image-") && file_name.ends_with(&suffix) {
+                    let page_str = &file_name["image-".len()..file_name.len() - suffix.len()];
                     if let Ok(page_number) = page_str.parse::<u32>() {
                         image_files.push((page_number, entry.path()));
                     }
@@ -114,8 +448,8 @@ impl FileInfo {
             image_files.sort_by_key(|(page_number, _)| *page_number);
 
             let mut results = Vec::new();
-            for (_, image_path) in image_files {
-                results.push(FileInfo::process_image(&image_path).await?);
+            for (page_number, image_path) in image_files {
+                results.push((page_number, FileInfo::process_image(&image_path).await?));
             }
 
             Ok(results)
@@ -125,3 +459,217 @@ impl FileInfo {
         result
     }
 }
+
+/// `pdftotext` ends every page with a form feed.
+fn split_pages(text: &str) -> Vec<String> {
+    let mut pages: Vec<_> = text.split('\x0c').map(|page| page.to_string()).collect();
+    pages.pop();
+
+    pages
+}
+
+/// Returns the text of those `selected` pages whose text layer has at least
+/// `min_text_length` non-whitespace characters, or `None` if `extracted` does
+/// not match the `page_count` of the document.
+fn text_pages(
+    extracted: Vec<String>,
+    page_count: u32,
+    selected: &[u32],
+    min_text_length: usize,
+) -> Option<HashMap<u32, String>> {
+    if extracted.len() != page_count as usize {
+        return None;
+    }
+
+    Some(
+        selected
+            .iter()
+            .map(|page| (*page, extracted[*page as usize - 1].clone()))
+            .filter(|(_, text)| {
+                text.chars().filter(|c| !c.is_whitespace()).count() >= min_text_length
+            })
+            .collect(),
+    )
+}
+
+/// Looks up `key` in the page dictionary `id` or, as it is inheritable, in its parents.
+fn inherited<'a>(
+    document: &'a lopdf::Document,
+    id: lopdf::ObjectId,
+    key: &[u8],
+) -> Option<&'a lopdf::Object> {
+    let mut dictionary = document.get_dictionary(id).ok()?;
+    loop {
+        if let Ok(value) = dictionary.get_deref(key, document) {
+            return Some(value);
+        }
+        dictionary = document
+            .get_dictionary(dictionary.get(b"Parent").ok()?.as_reference().ok()?)
+            .ok()?;
+    }
+}
+
+/// The longest side in points of the page `id` as rendered, i.e. of its crop box.
+fn page_extent(document: &lopdf::Document, id: lopdf::ObjectId) -> Option<f32> {
+    let page_box = inherited(document, id, b"CropBox")
+        .or_else(|| inherited(document, id, b"MediaBox"))?
+        .as_array()
+        .ok()?
+        .iter()
+        .map(|value| value.as_float().ok())
+        .collect::<Option<Vec<_>>>()?;
+    match page_box[..] {
+        [x1, y1, x2, y2] => Some((x2 - x1).abs().max((y2 - y1).abs())),
+        _ => None,
+    }
+}
+
+/// The resolution `pdftoppm` renders at unless told otherwise.
+const DEFAULT_DPI: u32 = 150;
+
+/// The resolution to render a page whose longest side is `extent` points at:
+/// `dpi`, lowered only as far as needed to keep the page within `max_dimension` pixels.
+fn page_resolution(
+    extent: Option<f32>,
+    dpi: Option<u32>,
+    max_dimension: Option<u32>,
+) -> Option<u32> {
+    let (Some(extent), Some(max_dimension)) = (extent, max_dimension) else {
+        return dpi;
+    };
+    let capped = (max_dimension as f32 * 72.0 / extent).floor() as u32;
+    if capped < dpi.unwrap_or(DEFAULT_DPI) {
+        Some(capped.max(1))
+    } else {
+        dpi
+    }
+}
+
+/// Groups consecutive pages rendered at the same resolution.
+fn page_ranges(pages: &[(u32, Option<u32>)]) -> Vec<(u32, u32, Option<u32>)> {
+    let mut ranges: Vec<(u32, u32, Option<u32>)> = Vec::new();
+    for (page, dpi) in pages {
+        match ranges.last_mut() {
+            Some((_, last, last_dpi)) if *last + 1 == *page && last_dpi == dpi => *last = *page,
+            _ => ranges.push((*page, *page, *dpi)),
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use tempdir::TempDir;
+
+    #[rstest]
+    #[case("scan.jpg", b"\x89PNG\r\n\x1a\n\0\0\0\0", "png, expected jpeg")]
+    #[case("scan.tif", b"%PDF-1.7\n", "pdf, expected tiff")]
+    #[case("letter.docx", b"%PDF-1.7\n", "pdf, expected zip")]
+    #[case("letter.doc", b"PK\x03\x04\x14\0\x06\0", "zip, expected ole")]
+    #[case("mail.msg", b"%PDF-1.7\n", "pdf, expected ole")]
+    #[tokio::test]
+    async fn test_check_mismatch(
+        #[case] filename: &str,
+        #[case] content: &[u8],
+        #[case] detected: &str,
+    ) {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let path = tmp.path().join(filename);
+        fs::write(&path, content).await.unwrap();
+        match FileInfo::new(path).unwrap().check().await {
+            Err(Error::UnsupportedFileTypeError(_, file_type)) => assert_eq!(file_type, detected),
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    #[rstest]
+    #[case("Invoice\x0cTerms\x0c", vec!["Invoice", "Terms"])]
+    #[case("Invoice\x0c\x0c", vec!["Invoice", ""])]
+    #[case("", vec![])]
+    fn test_split_pages(#[case] text: &str, #[case] pages: Vec<&str>) {
+        assert_eq!(split_pages(text), pages);
+    }
+
+    #[rstest]
+    #[case(3, &[1, 2, 3], 10, Some(vec![1, 3]))]
+    #[case(3, &[2, 3], 10, Some(vec![3]))]
+    #[case(3, &[1, 2, 3], 0, Some(vec![1, 2, 3]))]
+    #[case(3, &[1, 2, 3], 100, Some(vec![]))]
+    #[case(4, &[1, 2, 3], 10, None)]
+    #[case(2, &[1, 2], 10, None)]
+    fn test_text_pages(
+        #[case] page_count: u32,
+        #[case] selected: &[u32],
+        #[case] min_text_length: usize,
+        #[case] pages: Option<Vec<u32>>,
+    ) {
+        let extracted = vec![
+            "Invoice No. 2024/117\n".to_string(),
+            "  \n 12\n".to_string(),
+            "Terms and conditions".to_string(),
+        ];
+        let texts = text_pages(extracted.clone(), page_count, selected, min_text_length);
+        assert_eq!(
+            texts.as_ref().map(|texts| {
+                let mut pages: Vec<_> = texts.keys().cloned().collect();
+                pages.sort();
+                pages
+            }),
+            pages
+        );
+        for (page, text) in texts.into_iter().flatten() {
+            assert_eq!(text, extracted[page as usize - 1]);
+        }
+    }
+
+    #[rstest]
+    #[case(Some(842.0), Some(100), Some(1600), Some(100))]
+    #[case(Some(842.0), Some(300), Some(1600), Some(136))]
+    #[case(Some(842.0), None, Some(1600), Some(136))]
+    #[case(Some(842.0), None, Some(2000), None)]
+    #[case(Some(842.0), None, Some(1000), Some(85))]
+    #[case(Some(842.0), Some(300), None, Some(300))]
+    #[case(None, Some(300), Some(1600), Some(300))]
+    fn test_page_resolution(
+        #[case] extent: Option<f32>,
+        #[case] dpi: Option<u32>,
+        #[case] max_dimension: Option<u32>,
+        #[case] resolution: Option<u32>,
+    ) {
+        assert_eq!(page_resolution(extent, dpi, max_dimension), resolution);
+    }
+
+    #[rstest]
+    #[case(&[(1, None), (2, None), (4, None)], vec![(1, 2, None), (4, 4, None)])]
+    #[case(&[(1, Some(136)), (2, Some(100)), (3, Some(100))], vec![(1, 1, Some(136)), (2, 3, Some(100))])]
+    fn test_page_ranges(
+        #[case] pages: &[(u32, Option<u32>)],
+        #[case] ranges: Vec<(u32, u32, Option<u32>)>,
+    ) {
+        assert_eq!(page_ranges(pages), ranges);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_pdf_page_extents() {
+        let extents = FileInfo::pdf_page_extents(&PathBuf::from("files/example.pdf"))
+            .await
+            .unwrap();
+        assert!(!extents.is_empty());
+        assert!(extents.values().all(|extent| *extent > 0.0));
+    }
+
+    #[rstest]
+    fn test_unsupported_extension() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let path = tmp.path().join("letter.pages");
+        std::fs::write(&path, b"PK\x03\x04\x14\0\x06\0").unwrap();
+        match FileInfo::new(path) {
+            Err(Error::UnsupportedFileTypeError(_, file_type)) => assert_eq!(file_type, "zip"),
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use crate::error::{Error, Result};
+use crate::file_type::FileType;
 use crate::paths::{Location, Paths};
 use crate::util::file_exists;
-use crate::util::{make_unique_path, move_file};
+use crate::util::move_file;
 use std::ffi::OsString;
 use std::path::PathBuf;
 use strum::IntoEnumIterator;
@@ -21,14 +22,14 @@ impl FileObject {
             paths,
             filename: filepath
                 .file_name()
-                .ok_or_else(|| Error::UnsupportedFileTypeError(filepath.clone()))?
+                .ok_or_else(|| {
+                    Error::UnsupportedFileTypeError(filepath.clone(), FileType::Unknown.to_string())
+                })?
                 .to_os_string(),
         };
         for location in Location::iter() {
-            if location != Location::Inbox {
-                if file_exists(&file.make_path(location)) {
-                    return Err(Error::FileExists(filepath));
-                }
+            if location != Location::Inbox && file_exists(&file.make_path(location)) {
+                return Err(Error::FileExists(filepath));
             }
         }
         Ok(file)
@@ -38,15 +39,6 @@ impl FileObject {
         self.paths.make_root(location).join(self.filename.clone())
     }
 
-    pub async fn make_path_with_new_filename(
-        &self,
-        location: Location,
-        path: PathBuf,
-        filename: String,
-    ) -> Result<PathBuf> {
-        Ok(make_unique_path(self.paths.make_root(location).join(path), filename).await?)
-    }
-
     pub fn get_path(&self) -> PathBuf {
         self.make_path(self.current_location)
     }
@@ -0,0 +1,109 @@
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+/// Number of bytes needed to detect a file type.
+const HEADER_BYTES: usize = 16;
+
+const HEIC_BRANDS: &[&[u8]] = &[
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+];
+
+/// The type of a file as determined by its magic bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Pdf,
+    Png,
+    Jpeg,
+    Tiff,
+    Webp,
+    Heic,
+    Avif,
+    Gif,
+    Bmp,
+    Zip,
+    Ole,
+    Rtf,
+    /// E-mails have no magic bytes, this is never detected.
+    Email,
+    Unknown,
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl FileType {
+    pub fn detect(header: &[u8]) -> FileType {
+        let brand = header.get(8..12);
+        if header.starts_with(b"%PDF-") {
+            FileType::Pdf
+        } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+            FileType::Png
+        } else if header.starts_with(b"\xff\xd8\xff") {
+            FileType::Jpeg
+        } else if [b"II*\0", b"MM\0*", b"II+\0", b"MM\0+"]
+            .iter()
+            .any(|magic| header.starts_with(*magic))
+        {
+            FileType::Tiff
+        } else if header.starts_with(b"RIFF") && brand == Some(b"WEBP") {
+            FileType::Webp
+        } else if header.get(4..8) == Some(b"ftyp") && brand == Some(b"avif") {
+            FileType::Avif
+        } else if header.get(4..8) == Some(b"ftyp")
+            && brand.is_some_and(|b| HEIC_BRANDS.contains(&b))
+        {
+            FileType::Heic
+        } else if header.starts_with(b"GIF8") {
+            FileType::Gif
+        } else if header.starts_with(b"BM") {
+            FileType::Bmp
+        } else if header.starts_with(b"PK\x03\x04") {
+            FileType::Zip
+        } else if header.starts_with(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1") {
+            FileType::Ole
+        } else if header.starts_with(b"{\\rtf") {
+            FileType::Rtf
+        } else {
+            FileType::Unknown
+        }
+    }
+
+    /// Detects the type of the file at `path`. Files that cannot be read are `Unknown`.
+    pub fn detect_file(path: &Path) -> FileType {
+        let mut header = Vec::with_capacity(HEADER_BYTES);
+        match std::fs::File::open(path)
+            .and_then(|file| file.take(HEADER_BYTES as u64).read_to_end(&mut header))
+        {
+            Ok(_) => FileType::detect(&header),
+            Err(_) => FileType::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(b"%PDF-1.7\n", FileType::Pdf)]
+    #[case(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", FileType::Png)]
+    #[case(b"\xff\xd8\xff\xe1\0\0Exif", FileType::Jpeg)]
+    #[case(b"II*\0\x08\0\0\0", FileType::Tiff)]
+    #[case(b"MM\0*\0\0\0\x08", FileType::Tiff)]
+    #[case(b"RIFF\0\0\0\0WEBPVP8 ", FileType::Webp)]
+    #[case(b"\0\0\0\x18ftypheic\0\0\0\0", FileType::Heic)]
+    #[case(b"\0\0\0\x18ftypmif1\0\0\0\0", FileType::Heic)]
+    #[case(b"\0\0\0\x1cftypavif\0\0\0\0", FileType::Avif)]
+    #[case(b"\0\0\0\x18ftypisom\0\0\0\0", FileType::Unknown)]
+    #[case(b"PK\x03\x04\x14\0", FileType::Zip)]
+    #[case(b"{\\rtf1\\ansi", FileType::Rtf)]
+    #[case(b"", FileType::Unknown)]
+    fn test_detect(#[case] header: &[u8], #[case] file_type: FileType) {
+        assert_eq!(FileType::detect(header), file_type);
+    }
+}
@@ -1,19 +1,31 @@
-use crate::chatgpt::query_ai;
+use crate::analyzer::{DocumentAnalyzer, DocumentContext};
+use crate::approval::{propose, run_approval_scheduler};
+use crate::email::{Email, Origin};
 use crate::error::{Error, Result};
+use crate::error_record::{run_requeue_scheduler, ErrorRecord};
 use crate::file_info::FileInfo;
 use crate::file_object::FileObject;
+use crate::office;
+use crate::outbox::file_document;
 use crate::paths::Location;
-use crate::pdf::update_metadata;
-use crate::profile::Profile;
+use crate::photo;
+use crate::profile::{OfficeProfile, Profile};
+use crate::review::{run_review_scheduler, send_to_review, ACCEPTED};
+use crate::sanitize::{sanitize_component, MAX_COMPONENT_BYTES};
+use crate::template::variable_depth;
+use crate::util::{list_dirs_at, make_unique_path, UNIQUE_SUFFIX_BYTES};
+use chrono::{DateTime, Local};
+use filetime::{set_file_mtime, FileTime};
 use notify::event::CreateKind;
 use notify::{Event, EventKind};
+use std::collections::HashMap;
 use std::future::Future;
 use std::marker::Send;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tokio::fs;
 use tokio::fs::create_dir_all;
-use tokio::io::AsyncWriteExt;
-use tokio::task::JoinSet;
+use tokio::task::{JoinHandle, JoinSet};
 use tokio::time::{sleep, Duration};
 
 pub trait EventHandler: Send + 'static {
@@ -29,13 +41,30 @@ pub trait EventHandler: Send + 'static {
     }
 }
 
-pub struct Handler {
+/// The modification time of the file at `path`.
+async fn read_mtime(path: &Path) -> Option<DateTime<Local>> {
+    fs::metadata(path)
+        .await
+        .ok()?
+        .modified()
+        .ok()
+        .map(DateTime::<Local>::from)
+}
+
+/// The origins of attachments put into the inbox, by their path.
+type Origins = tokio::sync::Mutex<HashMap<PathBuf, Origin>>;
+
+pub struct Handler<A: DocumentAnalyzer> {
     profile: Profile,
+    analyzer: Arc<A>,
     tasks: JoinSet<()>,
     concurrency: u8,
+    schedulers: Vec<JoinHandle<()>>,
+    image_group: Arc<Mutex<Vec<PathBuf>>>,
+    origins: Arc<Origins>,
 }
 
-impl EventHandler for Handler {
+impl<A: DocumentAnalyzer> EventHandler for Handler<A> {
     async fn handle_event(&mut self, event: Event) {
         match event {
             Event {
@@ -65,21 +94,53 @@ impl EventHandler for Handler {
     }
 }
 
-impl Handler {
-    pub async fn new(profile: Profile, concurrency: u8) -> Result<Self> {
+impl<A: DocumentAnalyzer> Handler<A> {
+    pub async fn new(profile: Profile, analyzer: A, concurrency: u8) -> Result<Self> {
+        profile.check()?;
         create_dir_all(profile.paths.make_root(Location::Inbox)).await?;
         create_dir_all(profile.paths.make_root(Location::Outbox)).await?;
         create_dir_all(profile.paths.make_root(Location::Transit)).await?;
         create_dir_all(profile.paths.make_root(Location::Processed)).await?;
         create_dir_all(profile.paths.make_root(Location::Error)).await?;
+        let mut schedulers = Vec::new();
+        if let Some(ref requeue) = profile.requeue {
+            schedulers.push(tokio::task::spawn(run_requeue_scheduler(
+                profile.paths.clone(),
+                requeue.clone(),
+            )));
+        }
+        if profile.review.is_some() {
+            create_dir_all(profile.paths.make_root(Location::Review).join(ACCEPTED)).await?;
+            schedulers.push(tokio::task::spawn(run_review_scheduler(profile.clone())));
+        }
+        if profile.approval {
+            create_dir_all(profile.paths.make_root(Location::Pending)).await?;
+            schedulers.push(tokio::task::spawn(run_approval_scheduler(profile.clone())));
+        }
         Ok(Handler {
             profile,
+            analyzer: Arc::new(analyzer),
             tasks: JoinSet::new(),
             concurrency,
+            schedulers,
+            image_group: Arc::new(Mutex::new(Vec::new())),
+            origins: Arc::new(Origins::new(HashMap::new())),
         })
     }
 
     async fn handle_file(&mut self, filepath: PathBuf) {
+        let origin = self
+            .origins
+            .lock()
+            .await
+            .remove(&filepath)
+            .unwrap_or_default();
+        let is_image = self.profile.images.pdf
+            && FileInfo::new(filepath.clone()).is_ok_and(|file_info| file_info.is_image());
+        let is_attachment = origin.email.is_some();
+        if is_image && !is_attachment && self.join_image_group(&filepath) {
+            return;
+        }
         while self.tasks.len() >= self.concurrency.into() {
             self.tasks
                 .join_next()
@@ -87,19 +148,64 @@ impl Handler {
                 .expect("Cannot be empty")
                 .expect("Task should not panic");
         }
-        self.tasks.spawn(Handler::handle_file_entry_point(
-            self.profile.clone(),
-            filepath.clone(),
-        ));
+        if !is_image {
+            self.tasks.spawn(Handler::handle_file_entry_point(
+                self.profile.clone(),
+                self.analyzer.clone(),
+                self.origins.clone(),
+                filepath.clone(),
+                origin,
+            ));
+        } else if let (Some(seconds), false) = (self.profile.images.group_seconds, is_attachment) {
+            self.image_group
+                .lock()
+                .expect("Image group lock poisoned")
+                .push(filepath);
+            self.tasks.spawn(Handler::handle_image_group_entry_point(
+                self.profile.clone(),
+                self.analyzer.clone(),
+                self.image_group.clone(),
+                seconds,
+            ));
+        } else {
+            self.tasks.spawn(Handler::handle_images_entry_point(
+                self.profile.clone(),
+                self.analyzer.clone(),
+                vec![filepath],
+                origin,
+            ));
+        }
+    }
+
+    /// Adds the image to the group currently being collected, if there is one.
+    fn join_image_group(&self, filepath: &Path) -> bool {
+        let mut group = self.image_group.lock().expect("Image group lock poisoned");
+        if self.profile.images.group_seconds.is_none() || group.is_empty() {
+            return false;
+        }
+        group.push(filepath.to_path_buf());
+
+        true
     }
 
     async fn wait(self) {
+        for scheduler in self.schedulers {
+            scheduler.abort();
+        }
         self.tasks.join_all().await;
     }
 
-    async fn handle_file_entry_point(profile: Profile, filepath: PathBuf) {
+    async fn handle_file_entry_point(
+        profile: Profile,
+        analyzer: Arc<A>,
+        origins: Arc<Origins>,
+        filepath: PathBuf,
+        origin: Origin,
+    ) {
         log::info!("Processing {filepath:?}");
-        match Handler::handle_file_transit(profile, filepath.clone()).await {
+        match Handler::handle_file_transit(profile, analyzer, origins, filepath.clone(), origin)
+            .await
+        {
             Ok(_) => {
                 log::info!("Processed {:?}", filepath);
             }
@@ -109,61 +215,411 @@ impl Handler {
         }
     }
 
-    async fn handle_file_transit(profile: Profile, filepath: PathBuf) -> Result<()> {
+    async fn handle_file_transit(
+        profile: Profile,
+        analyzer: Arc<A>,
+        origins: Arc<Origins>,
+        filepath: PathBuf,
+        origin: Origin,
+    ) -> Result<()> {
         let mut file = FileObject::new(profile.paths.clone(), filepath)?;
         log::debug!("Processing as {file:?}");
-        match Handler::handle_file_processing(profile, &mut file).await {
-            Ok(_) => Ok(()),
+        // A requeued PDF converted from an office document keeps its original.
+        let original = ErrorRecord::load(&file.make_path(Location::Error))
+            .await
+            .and_then(|record| record.original);
+        let result = match (profile.office.clone(), FileInfo::new(file.get_path())) {
+            (Some(office), Ok(FileInfo::OFFICE(_))) => {
+                return Handler::handle_office_transit(
+                    profile, analyzer, &office, &mut file, origin,
+                )
+                .await;
+            }
+            (_, Ok(FileInfo::EMAIL(_))) => {
+                Handler::handle_email_processing(profile, analyzer, &origins, &mut file).await
+            }
+            _ => {
+                Handler::handle_file_processing(
+                    profile,
+                    analyzer,
+                    &mut file,
+                    original.as_deref(),
+                    origin,
+                )
+                .await
+            }
+        };
+        Self::finish(&mut file, result, original.as_deref()).await
+    }
+
+    /// Processes the body of the e-mail as a document. Once that succeeded, its
+    /// supported attachments are put into the inbox, where they are processed as
+    /// documents of their own, linked to the e-mail by its ID. Extracting them only
+    /// then keeps a failed e-mail from leaving them behind when it is requeued.
+    async fn handle_email_processing(
+        profile: Profile,
+        analyzer: Arc<A>,
+        origins: &Origins,
+        file: &mut FileObject,
+    ) -> Result<()> {
+        log::debug!("Waiting for file");
+        sleep(Duration::from_secs(1)).await;
+        Self::wait_for_document(file).await?;
+        file.rename(Location::Transit).await?;
+
+        let email = Email::read(&file.get_path()).await?;
+        let stem = file
+            .get_path()
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let origin = Origin {
+            email: Some(email.id.clone()),
+            sender: email.sender.clone(),
+        };
+        let mtime = read_mtime(&file.get_path()).await;
+        Self::handle_document(profile.clone(), analyzer, file, None, mtime, origin.clone()).await?;
+
+        for attachment in email.attachments {
+            let name = sanitize_component(
+                &format!("{stem}-{}", attachment.name),
+                MAX_COMPONENT_BYTES - UNIQUE_SUFFIX_BYTES,
+            );
+            if FileInfo::new(PathBuf::from(&name)).is_err() {
+                log::info!(
+                    "Skipping unsupported attachment {:?} of {file:?}",
+                    attachment.name
+                );
+                continue;
+            }
+            // The e-mail has been handled, failing it now would process it again.
+            if let Err(err) =
+                Self::extract_attachment(&profile, origins, &origin, mtime, name, attachment.data)
+                    .await
+            {
+                log::error!(
+                    "Unable to extract attachment {:?} of {file:?}: {err}",
+                    attachment.name
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn extract_attachment(
+        profile: &Profile,
+        origins: &Origins,
+        origin: &Origin,
+        mtime: Option<DateTime<Local>>,
+        name: String,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let mut origins = origins.lock().await;
+        let attachment_path =
+            make_unique_path(profile.paths.make_root(Location::Inbox), name).await?;
+        fs::write(&attachment_path, data).await?;
+        // The attachment dates from the e-mail, not from its extraction.
+        if let Some(mtime) = mtime {
+            set_file_mtime(&attachment_path, FileTime::from_system_time(mtime.into()))?;
+        }
+        origins.insert(attachment_path.clone(), origin.clone());
+        log::info!("Extracted attachment {attachment_path:?}");
+
+        Ok(())
+    }
+
+    /// Converts the office document to PDF and processes the PDF. The original
+    /// is moved to the processed location.
+    async fn handle_office_transit(
+        profile: Profile,
+        analyzer: Arc<A>,
+        office: &OfficeProfile,
+        original: &mut FileObject,
+        origin: Origin,
+    ) -> Result<()> {
+        log::debug!("Waiting for file");
+        sleep(Duration::from_secs(1)).await;
+        let result = async {
+            Self::wait_for_document(original).await?;
+            original.rename(Location::Transit).await?;
+            let file = Self::make_pdf_object(&profile, original)?;
+            office::convert(office, &original.get_path(), &file.get_path()).await?;
+            Ok(file)
+        }
+        .await;
+        let mut file = match result {
+            Ok(file) => file,
+            Err(err) => {
+                Self::fail(original, &err, None).await;
+                return Err(err);
+            }
+        };
+        original.rename(Location::Processed).await?;
+        let mtime = read_mtime(&original.get_path()).await;
+        let original_name = office.keep_original.then(|| {
+            original
+                .get_path()
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        });
+        log::debug!("Processing as {file:?}");
+        let result = Handler::handle_document(
+            profile,
+            analyzer,
+            &mut file,
+            original_name.as_deref(),
+            mtime,
+            origin,
+        )
+        .await;
+        Self::finish(&mut file, result, original_name.as_deref()).await
+    }
+
+    /// Creates the object for a PDF in transit named after `source`.
+    fn make_pdf_object(profile: &Profile, source: &FileObject) -> Result<FileObject> {
+        let mut filename = source
+            .get_path()
+            .file_stem()
+            .unwrap_or_default()
+            .to_os_string();
+        filename.push(".pdf");
+        let mut file = FileObject::new(profile.paths.clone(), PathBuf::from(filename))?;
+        file.current_location = Location::Transit;
+
+        Ok(file)
+    }
+
+    /// Collects images until no more arrive for `seconds` and processes them as one document.
+    async fn handle_image_group_entry_point(
+        profile: Profile,
+        analyzer: Arc<A>,
+        group: Arc<Mutex<Vec<PathBuf>>>,
+        seconds: u64,
+    ) {
+        let mut count = 0;
+        let filepaths = loop {
+            sleep(Duration::from_secs(seconds)).await;
+            let mut group = group.lock().expect("Image group lock poisoned");
+            if group.len() == count {
+                break std::mem::take(&mut *group);
+            }
+            count = group.len();
+        };
+        Handler::handle_images_entry_point(profile, analyzer, filepaths, Origin::default()).await;
+    }
+
+    async fn handle_images_entry_point(
+        profile: Profile,
+        analyzer: Arc<A>,
+        filepaths: Vec<PathBuf>,
+        origin: Origin,
+    ) {
+        log::info!("Processing {filepaths:?} as one document");
+        match Handler::handle_images_transit(profile, analyzer, &filepaths, origin).await {
+            Ok(_) => {
+                log::info!("Processed {:?}", filepaths);
+            }
+            Err(err) => {
+                log::error!("Unable to process images: {:?}: {}", filepaths, err);
+            }
+        }
+    }
+
+    async fn handle_images_transit(
+        profile: Profile,
+        analyzer: Arc<A>,
+        filepaths: &[PathBuf],
+        origin: Origin,
+    ) -> Result<()> {
+        log::debug!("Waiting for files");
+        sleep(Duration::from_secs(1)).await;
+        let mut images = Vec::new();
+        for filepath in filepaths {
+            let mut image = match FileObject::new(profile.paths.clone(), filepath.clone()) {
+                Ok(image) => image,
+                Err(err) => {
+                    log::error!("Unable to process file: {:?}: {}", filepath, err);
+                    continue;
+                }
+            };
+            let result = async {
+                Self::wait_for_document(&image).await?;
+                image.rename(Location::Transit).await
+            }
+            .await;
+            match result {
+                Ok(_) => images.push(image),
+                Err(err) => {
+                    log::error!("Unable to process file: {:?}: {}", filepath, err);
+                    Self::fail(&mut image, &err, None).await;
+                }
+            }
+        }
+        if images.is_empty() {
+            return Err(Error::Other(
+                "None of the images could be processed".to_string(),
+            ));
+        }
+
+        // The earliest of the images stands in for the document.
+        let mut mtimes = Vec::new();
+        for image in &images {
+            mtimes.extend(read_mtime(&image.get_path()).await);
+        }
+        let mtime = mtimes.into_iter().min();
+        let mut file = match Self::convert_images(&profile, &images).await {
+            Ok(file) => file,
             Err(err) => {
-                if let Err(err) = file.rename(Location::Error).await {
-                    log::error!("Unable to move file to error location: {:?}: {}", file, err);
+                for image in &mut images {
+                    Self::fail(image, &err, None).await;
                 }
+                return Err(err);
+            }
+        };
+        log::debug!("Processing as {file:?}");
+        if let Err(err) =
+            Handler::handle_document(profile, analyzer, &mut file, None, mtime, origin).await
+        {
+            // The images are kept for requeueing, the PDF is only an intermediate.
+            fs::remove_file(file.get_path())
+                .await
+                .inspect_err(|e| log::warn!("Unable to remove {file:?}: {e}"))
+                .ok();
+            for image in &mut images {
+                Self::fail(image, &err, None).await;
+            }
+            return Err(err);
+        }
+        for image in &mut images {
+            image.rename(Location::Processed).await?;
+            ErrorRecord::remove(&image.make_path(Location::Error))
+                .await
+                .inspect_err(|e| log::warn!("Unable to remove error record: {image:?}: {e:?}"))
+                .ok();
+        }
+
+        Ok(())
+    }
+
+    /// Combines the images in transit into a single PDF in transit,
+    /// named after the first image.
+    async fn convert_images(profile: &Profile, images: &[FileObject]) -> Result<FileObject> {
+        let file = Self::make_pdf_object(profile, &images[0])?;
+        let mut data = Vec::new();
+        for image in images {
+            data.push(FileInfo::new(image.get_path())?.read_image().await?);
+        }
+        photo::convert_to_pdf(data, &file.get_path()).await?;
+
+        Ok(file)
+    }
+
+    async fn finish(
+        file: &mut FileObject,
+        result: Result<()>,
+        original: Option<&str>,
+    ) -> Result<()> {
+        match result {
+            Ok(_) => {
+                ErrorRecord::remove(&file.make_path(Location::Error))
+                    .await
+                    .inspect_err(|e| log::warn!("Unable to remove error record: {file:?}: {e:?}"))
+                    .ok();
+                Ok(())
+            }
+            Err(err) => {
+                Self::fail(file, &err, original).await;
                 Err(err)
             }
         }
     }
 
-    async fn handle_file_processing(profile: Profile, file: &mut FileObject) -> Result<()> {
+    /// Moves the file to the error location and records `err`, along with the
+    /// original of a converted document so that it is kept when requeued.
+    async fn fail(file: &mut FileObject, err: &Error, original: Option<&str>) {
+        if let Err(err) = file.rename(Location::Error).await {
+            log::error!("Unable to move file to error location: {:?}: {}", file, err);
+        } else {
+            ErrorRecord::record(&file.get_path(), err, original)
+                .await
+                .inspect_err(|e| log::warn!("Unable to write error record: {file:?}: {e:?}"))
+                .ok();
+        }
+    }
+
+    async fn handle_file_processing(
+        profile: Profile,
+        analyzer: Arc<A>,
+        file: &mut FileObject,
+        original: Option<&str>,
+        origin: Origin,
+    ) -> Result<()> {
         log::debug!("Waiting for file");
         sleep(Duration::from_secs(1)).await;
-        FileInfo::new(file.get_path())?;
-        Handler::wait_for_document(file).await?;
+        Self::wait_for_document(file).await?;
         file.rename(Location::Transit).await?;
 
+        // A requeued PDF converted from an office document dates from the conversion.
+        let input = match original {
+            Some(original) => profile.paths.make_root(Location::Processed).join(original),
+            None => file.get_path(),
+        };
+        let mtime = read_mtime(&input).await;
+        Self::handle_document(profile, analyzer, file, original, mtime, origin).await
+    }
+
+    /// Analyzes the document in transit and files it, or holds it back for review
+    /// or approval. `mtime` is that of the input the document was made from,
+    /// which for converted documents is not the document itself.
+    async fn handle_document(
+        profile: Profile,
+        analyzer: Arc<A>,
+        file: &mut FileObject,
+        original: Option<&str>,
+        mtime: Option<DateTime<Local>>,
+        origin: Origin,
+    ) -> Result<()> {
         let file_info = FileInfo::new(file.get_path())?;
-        let (classes, sources) = Handler::determine_classes_sources(&profile).await?;
-        let document_data = query_ai(profile.chatgpt, file_info, classes, sources).await?;
-        let dst_path_pdf = file
-            .make_path_with_new_filename(
-                Location::Outbox,
-                document_data.make_path(),
-                document_data.make_filename("pdf"),
-            )
-            .await?;
-        update_metadata(file.get_path(), dst_path_pdf, &document_data)
-            .await
-            .map(|_| ())?;
-
-        if let Some(ref content) = document_data.content {
-            let content_path = file
-                .make_path_with_new_filename(
-                    Location::Outbox,
-                    document_data.make_path(),
-                    document_data.make_filename("content"),
-                )
-                .await?;
-            let mut out = fs::File::create(content_path).await?;
-            out.write_all(content.as_bytes()).await?;
-        }
-        let summary_path = file
-            .make_path_with_new_filename(
-                Location::Outbox,
-                document_data.make_path(),
-                document_data.make_filename("summary"),
-            )
-            .await?;
-        let mut out = fs::File::create(summary_path).await?;
-        out.write_all(document_data.summary.as_bytes()).await?;
+        let (classes, sources) = Self::determine_classes_sources(&profile).await?;
+        let pages = file_info.pages(&profile.pages).await?;
+        let context = DocumentContext {
+            classes,
+            sources,
+            mtime,
+            filename: file
+                .get_path()
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            sender: origin.sender,
+        };
+        let mut document_data = analyzer.analyze(pages, context).await?;
+        document_data.email = origin.email;
+        if profile.approval {
+            file.rename(Location::Pending).await?;
+            let proposal_path = propose(&file.get_path(), &document_data, original).await?;
+            log::info!("Set `approved: true` in {proposal_path:?} to file the document");
+            return Ok(());
+        }
+        if let Some(ref review) = profile.review {
+            let uncertain = review.uncertain_fields(&document_data);
+            if !uncertain.is_empty() {
+                log::info!(
+                    "Low confidence in {}, sending {file:?} to review",
+                    uncertain.join(", ")
+                );
+                let review_path =
+                    send_to_review(&profile, &file.get_path(), &document_data, original).await?;
+                log::info!("Move {review_path:?} to {ACCEPTED} to accept it");
+                return Ok(());
+            }
+        }
+        file_document(&profile, file.get_path(), &document_data, original).await?;
 
         file.rename(Location::Processed).await?;
 
@@ -171,48 +627,435 @@ impl Handler {
     }
 
     async fn determine_classes_sources(profile: &Profile) -> Result<(Vec<String>, Vec<String>)> {
-        let path = profile.paths.make_root(Location::Outbox);
-        let mut first_level_dirs = Vec::new();
-        let mut second_level_dirs = Vec::new();
-
-        let mut entries = fs::read_dir(&path).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            let dir_path = entry.path();
-            let metadata = entry.metadata().await?;
-            if metadata.is_dir() {
-                if let Some(dir_name) = dir_path.file_name() {
-                    first_level_dirs.push(dir_name.to_string_lossy().into_owned());
-                }
+        Ok((
+            Self::determine_known(profile, "class").await?,
+            Self::determine_known(profile, "source").await?,
+        ))
+    }
 
-                let mut sub_entries = fs::read_dir(&dir_path).await?;
-                while let Some(sub_entry) = sub_entries.next_entry().await? {
-                    let sub_dir_path = sub_entry.path();
-                    let sub_metadata = sub_entry.metadata().await?;
-                    if sub_metadata.is_dir() {
-                        if let Some(sub_dir_name) = sub_dir_path.file_name() {
-                            second_level_dirs.push(sub_dir_name.to_string_lossy().into_owned());
-                        }
-                    }
-                }
-            }
+    /// The existing directories of the outbox at the level of the layout path
+    /// that consists of just the variable `name`, if there is one.
+    async fn determine_known(profile: &Profile, name: &str) -> Result<Vec<String>> {
+        match variable_depth(&profile.layout.path, name)? {
+            Some(depth) => list_dirs_at(&profile.paths.make_root(Location::Outbox), depth).await,
+            None => Ok(Vec::new()),
         }
-
-        Ok((first_level_dirs, second_level_dirs))
     }
 
     async fn wait_for_document(file: &FileObject) -> Result<()> {
+        let file_info = FileInfo::new(file.get_path())?;
+        // Waiting would not make a missing converter appear.
+        if let FileInfo::HEIC(_) = file_info {
+            photo::heic_converter()?;
+        }
         let mut i = 6;
-        while let Err(_) = lopdf::Document::load(file.get_path()).await {
+        while let Err(err) = file_info.check().await {
+            if let Error::UnsupportedFileTypeError(..) | Error::DependencyMissingError(_) = err {
+                return Err(err);
+            }
             if tokio::fs::metadata(file.get_path()).await.is_err() {
                 return Err(Error::FileDisappearedError(file.get_path()));
             }
             log::info!("waiting for document to become ready: {file:?}");
             sleep(Duration::from_secs(10)).await;
-            i = i - 1;
+            i -= 1;
             if i == 0 {
-                return Err(Error::NotValidPdfError);
+                return Err(err);
             }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{DocumentData, CONFIDENCE_FIELDS};
+    use crate::file_info::Page;
+    use crate::profile::ReviewProfile;
+    use base64::{engine::general_purpose, Engine as _};
+    use chrono::TimeZone;
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use rstest::rstest;
+    use std::io::Cursor;
+    use tempdir::TempDir;
+
+    const EMAIL: &str = "From: Stadtwerke <billing@stadtwerke.example>\r
+Subject: Your invoice\r
+Message-ID: <invoice-117@stadtwerke.example>\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"outer\"\r
+\r
+--outer\r
+Content-Type: text/plain; charset=utf-8\r
+\r
+Please find your invoice attached.\r
+--outer\r
+Content-Type: image/png; name=\"scan.png\"\r
+Content-Disposition: attachment; filename=\"scan.png\"\r
+Content-Transfer-Encoding: base64\r
+\r
+{attachment}\r
+--outer--\r
+";
+
+    /// Returns the same data for every document, titled after its file
+    /// and dated by its modification time.
+    struct FakeAnalyzer {
+        confidence: f64,
+        fail: bool,
+    }
+
+    impl FakeAnalyzer {
+        fn new() -> Self {
+            FakeAnalyzer {
+                confidence: 100.0,
+                fail: false,
+            }
+        }
+    }
+
+    impl DocumentAnalyzer for FakeAnalyzer {
+        async fn analyze(
+            &self,
+            pages: Vec<Page>,
+            context: DocumentContext,
+        ) -> Result<DocumentData> {
+            if self.fail {
+                return Err(Error::Other("Analysis failed".to_string()));
+            }
+            Ok(DocumentData {
+                content: Some(self.transcribe(pages).await?),
+                summary: "Electricity bill".to_string(),
+                class: "invoice".to_string(),
+                source: "stadtwerke".to_string(),
+                keywords: vec!["electricity".to_string()],
+                title: Path::new(&context.filename)
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                date: context
+                    .mtime
+                    .map(|mtime| mtime.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+                confidence: CONFIDENCE_FIELDS
+                    .iter()
+                    .map(|field| (field.to_string(), self.confidence))
+                    .collect(),
+                ..DocumentData::default()
+            })
+        }
+
+        async fn transcribe(&self, pages: Vec<Page>) -> Result<String> {
+            Ok(pages
+                .into_iter()
+                .map(|page| match page {
+                    Page::Text(text) => text,
+                    Page::Image { .. } => "[image]".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+    }
+
+    fn make_profile(tmp: &TempDir) -> Profile {
+        let mut profile = Profile::default();
+        profile.paths = profile.paths.with_path(tmp.path().to_path_buf());
+        profile
+    }
+
+    fn make_png() -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(16, 16))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn make_docx() -> Vec<u8> {
+        b"PK\x03\x04\x14\0\0\0PK\x05\x06\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0".to_vec()
+    }
+
+    /// Puts the file into the inbox, dated 2024-03-01.
+    async fn put(profile: &Profile, filename: &str, data: &[u8]) -> PathBuf {
+        let path = profile.paths.make_root(Location::Inbox).join(filename);
+        fs::write(&path, data).await.unwrap();
+        let mtime = Local.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        set_file_mtime(&path, FileTime::from_system_time(mtime.into())).unwrap();
+        path
+    }
+
+    /// Processes the files as if they arrived in the inbox one after the other.
+    async fn process(profile: &Profile, analyzer: FakeAnalyzer, files: &[(&str, Vec<u8>)]) {
+        let mut handler = Handler::new(profile.clone(), analyzer, 2).await.unwrap();
+        for (filename, data) in files {
+            let path = put(profile, filename, data).await;
+            handler.handle_file(path).await;
+        }
+        handler.wait().await;
+    }
+
+    /// The names of the files in `location`, or in `dir` below it.
+    fn list(profile: &Profile, location: Location, dir: &str) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(profile.paths.make_root(location).join(dir)) else {
+            return Vec::new();
+        };
+        let mut filenames: Vec<_> = entries
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.path().is_file())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        filenames.sort();
+        filenames
+    }
+
+    const FILED: &str = "invoice/stadtwerke";
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_outbox() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let profile = make_profile(&tmp);
+        process(&profile, FakeAnalyzer::new(), &[("scan.png", make_png())]).await;
+
+        assert_eq!(
+            list(&profile, Location::Outbox, FILED),
+            [
+                "2024-03-01-scan.content",
+                "2024-03-01-scan.metadata.json",
+                "2024-03-01-scan.png",
+                "2024-03-01-scan.summary",
+            ]
+        );
+        assert_eq!(list(&profile, Location::Processed, ""), ["scan.png"]);
+        assert!(list(&profile, Location::Transit, "").is_empty());
+        assert!(list(&profile, Location::Error, "").is_empty());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_error() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let profile = make_profile(&tmp);
+        let analyzer = FakeAnalyzer {
+            fail: true,
+            ..FakeAnalyzer::new()
+        };
+        process(&profile, analyzer, &[("scan.png", make_png())]).await;
+
+        assert_eq!(
+            list(&profile, Location::Error, ""),
+            ["scan.png", "scan.png.error.json"]
+        );
+        assert!(list(&profile, Location::Outbox, FILED).is_empty());
+        assert!(list(&profile, Location::Transit, "").is_empty());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_heic_converter_missing() {
+        if photo::heic_converter().is_ok() {
+            return;
+        }
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let profile = make_profile(&tmp);
+        let heic = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            process(
+                &profile,
+                FakeAnalyzer::new(),
+                &[("scan.heic", heic.to_vec())],
+            ),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            list(&profile, Location::Error, ""),
+            ["scan.heic", "scan.heic.error.json"]
+        );
+        let error_path = profile.paths.make_root(Location::Error).join("scan.heic");
+        let record = ErrorRecord::load(&error_path).await.unwrap();
+        assert_eq!(record.variant, "DependencyMissingError");
+        assert!(!record.retryable);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_review() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let mut profile = make_profile(&tmp);
+        profile.review = Some(ReviewProfile::default());
+        let analyzer = FakeAnalyzer {
+            confidence: 10.0,
+            ..FakeAnalyzer::new()
+        };
+        process(&profile, analyzer, &[("scan.png", make_png())]).await;
+
+        assert_eq!(
+            list(&profile, Location::Review, ""),
+            ["2024-03-01-scan.png", "2024-03-01-scan.png.review.json"]
+        );
+        assert!(list(&profile, Location::Outbox, FILED).is_empty());
+        assert!(list(&profile, Location::Transit, "").is_empty());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_approval() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let mut profile = make_profile(&tmp);
+        profile.approval = true;
+        process(&profile, FakeAnalyzer::new(), &[("scan.png", make_png())]).await;
+
+        assert_eq!(
+            list(&profile, Location::Pending, ""),
+            [
+                "scan.png",
+                "scan.png.proposal.yaml",
+                "scan.png.validation.json"
+            ]
+        );
+        assert!(list(&profile, Location::Outbox, FILED).is_empty());
+        assert!(list(&profile, Location::Transit, "").is_empty());
+    }
+
+    #[rstest]
+    #[case(
+        false,
+        &["2024-03-01-scan-1.content", "2024-03-01-scan-1.pdf", "2024-03-01-scan-1.summary"],
+        &["scan-1.pdf", "scan-1.png", "scan-2.png"],
+        &[]
+    )]
+    #[case(
+        true,
+        &[],
+        &[],
+        &["scan-1.png", "scan-1.png.error.json", "scan-2.png", "scan-2.png.error.json"]
+    )]
+    #[tokio::test]
+    async fn test_image_group(
+        #[case] fail: bool,
+        #[case] filed: &[&str],
+        #[case] processed: &[&str],
+        #[case] failed: &[&str],
+    ) {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let mut profile = make_profile(&tmp);
+        profile.images.pdf = true;
+        profile.images.group_seconds = Some(1);
+        let analyzer = FakeAnalyzer {
+            fail,
+            ..FakeAnalyzer::new()
+        };
+        process(
+            &profile,
+            analyzer,
+            &[("scan-1.png", make_png()), ("scan-2.png", make_png())],
+        )
+        .await;
+
+        assert_eq!(list(&profile, Location::Outbox, FILED), filed);
+        assert_eq!(list(&profile, Location::Processed, ""), processed);
+        assert_eq!(list(&profile, Location::Error, ""), failed);
+        assert!(list(&profile, Location::Transit, "").is_empty());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_office() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let mut profile = make_profile(&tmp);
+        profile.office = Some(OfficeProfile {
+            // A stand-in converter that ignores its input.
+            command: [
+                "sh",
+                "-c",
+                "cp files/example.pdf \"$0\"",
+                "{outdir}",
+                "{input}",
+            ]
+            .map(String::from)
+            .to_vec(),
+            keep_original: true,
+            ..OfficeProfile::default()
+        });
+        process(
+            &profile,
+            FakeAnalyzer::new(),
+            &[("letter.docx", make_docx())],
+        )
+        .await;
+
+        assert_eq!(
+            list(&profile, Location::Outbox, FILED),
+            [
+                "2024-03-01-letter.content",
+                "2024-03-01-letter.docx",
+                "2024-03-01-letter.pdf",
+                "2024-03-01-letter.summary",
+            ]
+        );
+        assert_eq!(
+            list(&profile, Location::Processed, ""),
+            ["letter.docx", "letter.pdf"]
+        );
+        assert!(list(&profile, Location::Transit, "").is_empty());
+        assert!(list(&profile, Location::Error, "").is_empty());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_email() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let profile = make_profile(&tmp);
+        let email = EMAIL.replace(
+            "{attachment}",
+            &general_purpose::STANDARD.encode(make_png()),
+        );
+        let mut handler = Handler::new(profile.clone(), FakeAnalyzer::new(), 2)
+            .await
+            .unwrap();
+        let path = put(&profile, "mail.eml", email.as_bytes()).await;
+        handler.handle_file(path).await;
+        while handler.tasks.join_next().await.is_some() {}
+        assert_eq!(list(&profile, Location::Inbox, ""), ["mail-scan.png"]);
+        let attachment = profile
+            .paths
+            .make_root(Location::Inbox)
+            .join("mail-scan.png");
+        handler.handle_file(attachment).await;
+        handler.wait().await;
+
+        assert_eq!(
+            list(&profile, Location::Outbox, FILED),
+            [
+                "2024-03-01-mail-scan.content",
+                "2024-03-01-mail-scan.metadata.json",
+                "2024-03-01-mail-scan.png",
+                "2024-03-01-mail-scan.summary",
+                "2024-03-01-mail.content",
+                "2024-03-01-mail.eml",
+                "2024-03-01-mail.metadata.json",
+                "2024-03-01-mail.summary",
+            ]
+        );
+        let metadata = fs::read_to_string(
+            profile
+                .paths
+                .make_root(Location::Outbox)
+                .join(FILED)
+                .join("2024-03-01-mail-scan.metadata.json"),
+        )
+        .await
+        .unwrap();
+        assert!(metadata.contains("invoice-117@stadtwerke.example"));
+        assert_eq!(
+            list(&profile, Location::Processed, ""),
+            ["mail-scan.png", "mail.eml"]
+        );
+        assert!(list(&profile, Location::Error, "").is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::error::{Error, Result};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::Deserialize;
+use tokio::time::{timeout, Duration};
+
+/// Both APIs describe errors as `{"error": {"message": ...}}`.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    message: String,
+}
+
+/// Only the number of seconds is supported, not an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+fn status_error(status: StatusCode, headers: &HeaderMap, body: String) -> Error {
+    let message = serde_json::from_str::<ErrorResponse>(&body)
+        .map(|response| response.error.message)
+        .unwrap_or(body);
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        Error::RateLimitError(message, retry_after(headers))
+    } else {
+        Error::HttpStatusError(status.as_u16(), message)
+    }
+}
+
+/// Sends `request` and returns the body of the response. Unsuccessful responses
+/// become errors, rate limits keep the delay asked for in `Retry-After`.
+pub async fn send(request: RequestBuilder) -> Result<String> {
+    let response = timeout(Duration::from_secs(300), request.send()).await??;
+    log::trace!("received response");
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(status_error(status, &headers, body));
+    }
+
+    Ok(body)
+}
+
+/// Serves a response with the status line and headers `head` and `body` to the
+/// first connection and returns the URL to connect to.
+#[cfg(test)]
+pub async fn serve_once(head: &'static str, body: &'static str) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            match stream.read(&mut buf).await.unwrap() {
+                0 => break,
+                n => request.extend_from_slice(&buf[..n]),
+            }
+        }
+        let response = format!(
+            "{head}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(429, Some("7"), Some(Duration::from_secs(7)))]
+    #[case(429, Some("Wed, 21 Oct 2026 07:28:00 GMT"), None)]
+    #[case(429, None, None)]
+    fn test_rate_limit(
+        #[case] status: u16,
+        #[case] header: Option<&'static str>,
+        #[case] delay: Option<Duration>,
+    ) {
+        let mut headers = HeaderMap::new();
+        if let Some(header) = header {
+            headers.insert(RETRY_AFTER, HeaderValue::from_static(header));
+        }
+        let body = r#"{"error": {"message": "Slow down"}}"#.to_string();
+        match status_error(StatusCode::from_u16(status).unwrap(), &headers, body) {
+            Error::RateLimitError(message, retry_after) => {
+                assert_eq!(message, "Slow down");
+                assert_eq!(retry_after, delay);
+            }
+            err => panic!("Unexpected error: {err:?}"),
+        }
+    }
+
+    #[rstest]
+    fn test_status_error() {
+        let err = status_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &HeaderMap::new(),
+            "Service Unavailable".to_string(),
+        );
+        assert!(
+            matches!(err, Error::HttpStatusError(503, ref message) if message == "Service Unavailable")
+        );
+        assert!(err.is_transient());
+    }
+
+    #[rstest]
+    #[case(400, false, true)]
+    #[case(401, false, true)]
+    #[case(404, false, true)]
+    #[case(408, true, false)]
+    #[case(413, false, true)]
+    #[case(429, true, false)]
+    #[case(500, true, false)]
+    fn test_status_classification(
+        #[case] status: u16,
+        #[case] transient: bool,
+        #[case] permanent: bool,
+    ) {
+        let err = status_error(
+            StatusCode::from_u16(status).unwrap(),
+            &HeaderMap::new(),
+            String::new(),
+        );
+        assert_eq!(err.is_transient(), transient);
+        assert_eq!(err.is_permanent(), permanent);
+    }
+}
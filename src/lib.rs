@@ -1,14 +1,34 @@
+pub mod alias;
+pub mod analyzer;
+pub mod anthropic;
 pub mod api_key;
+pub mod approval;
 pub mod chatgpt;
+pub mod chunked;
 pub mod document;
+pub mod email;
 pub mod error;
+pub mod error_record;
 pub mod file_info;
 pub mod file_object;
+pub mod file_type;
 pub mod handler;
+pub mod http;
 pub mod main_loop;
+pub mod office;
+pub mod outbox;
 pub mod paths;
 pub mod pdf;
+pub mod photo;
 pub mod profile;
 pub mod profile_handler;
+pub mod prompt;
+pub mod retry;
+pub mod review;
+pub mod sanitize;
+pub mod sidecar;
+pub mod template;
+pub mod tesseract;
 pub mod util;
+pub mod validation;
 pub mod watcher;
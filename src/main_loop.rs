@@ -7,5 +7,5 @@ pub async fn run_main_loop(path: PathBuf) -> Result<()> {
     log::info!("Watching profiles: {:?}", path);
     let watcher_loop = WatcherLoop::new(path.clone(), ProfileHandler::new(path), false).await?;
 
-    Ok(watcher_loop.wait().await?)
+    watcher_loop.wait().await
 }
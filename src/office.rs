@@ -0,0 +1,208 @@
+use crate::error::{Error, Result};
+use crate::file_type::FileType;
+use crate::profile::OfficeProfile;
+use crate::template;
+use std::io::ErrorKind;
+use std::path::Path;
+use tempdir::TempDir;
+use tokio::fs;
+use tokio::process::Command;
+use tokio::time::{timeout, Duration};
+
+/// Maximum distance of the end of central directory record from the end of a ZIP file.
+const MAX_EOCD_DISTANCE: usize = 22 + u16::MAX as usize;
+
+const EXTENSIONS: &[(&str, FileType)] = &[
+    ("docx", FileType::Zip),
+    ("xlsx", FileType::Zip),
+    ("pptx", FileType::Zip),
+    ("odt", FileType::Zip),
+    ("ods", FileType::Zip),
+    ("odp", FileType::Zip),
+    ("doc", FileType::Ole),
+    ("xls", FileType::Ole),
+    ("ppt", FileType::Ole),
+    ("rtf", FileType::Rtf),
+];
+
+/// Returns the file type the content of an office document with `extension` has,
+/// if it is an office document.
+pub fn file_type(extension: &str) -> Option<FileType> {
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, file_type)| *file_type)
+}
+
+/// Checks that the office document `data` is complete. ZIP based formats end with
+/// the end of central directory record, other formats cannot be checked.
+pub fn is_complete(data: &[u8]) -> bool {
+    match FileType::detect(data) {
+        FileType::Zip => data[data.len().saturating_sub(MAX_EOCD_DISTANCE)..]
+            .windows(4)
+            .any(|window| window == b"PK\x05\x06"),
+        _ => true,
+    }
+}
+
+/// The variables available in the office command.
+const VARIABLES: &[&str] = &["input", "outdir"];
+
+/// Checks that the office command only uses known variables and is passed its input.
+pub fn check(profile: &OfficeProfile) -> Result<()> {
+    for arg in &profile.command {
+        template::check_with(arg, |name| VARIABLES.contains(&name))?;
+    }
+    if !profile
+        .command
+        .iter()
+        .map(|arg| template::uses(arg, "input"))
+        .collect::<Result<Vec<_>>>()?
+        .contains(&true)
+    {
+        return Err(Error::TemplateError(format!(
+            "`office.command` must contain {{input}}: {:?}",
+            profile.command
+        )));
+    }
+
+    Ok(())
+}
+
+/// Converts the office document at `src` to the PDF `dst` by running the configured
+/// command, which is expected to write a PDF into `{outdir}`.
+pub async fn convert(profile: &OfficeProfile, src: &Path, dst: &Path) -> Result<()> {
+    let tmp_dir = TempDir::new("mrdocument")?;
+    let input = src.to_string_lossy();
+    let outdir = tmp_dir.path().to_string_lossy();
+    let args = profile
+        .command
+        .iter()
+        .map(|arg| {
+            template::render_with(arg, |name| match name {
+                "input" => Ok(input.to_string()),
+                "outdir" => Ok(outdir.to_string()),
+                name => Err(Error::TemplateError(format!("Unknown variable: {name}"))),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| Error::OfficeConversionError("No command configured".to_string()))?;
+
+    log::info!("Converting {src:?} to PDF");
+    let output = timeout(
+        Duration::from_secs(profile.timeout),
+        Command::new(program).args(args).kill_on_drop(true).output(),
+    )
+    .await?
+    .map_err(|err| {
+        if err.kind() == ErrorKind::NotFound {
+            Error::DependencyMissingError(program.clone())
+        } else {
+            Error::from(err)
+        }
+    })?;
+    if !output.status.success() {
+        return Err(Error::OfficeConversionError(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+
+    let mut entries = fs::read_dir(tmp_dir.path()).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry
+            .path()
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+        {
+            fs::copy(entry.path(), dst).await?;
+            return Ok(());
+        }
+    }
+
+    Err(Error::OfficeConversionError(format!(
+        "No PDF was created: {}",
+        String::from_utf8_lossy(&output.stdout)
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn make_profile(command: &[&str]) -> OfficeProfile {
+        OfficeProfile {
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+            ..OfficeProfile::default()
+        }
+    }
+
+    #[rstest]
+    #[case(
+        b"PK\x03\x04\x14\0\0\0PK\x05\x06\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0",
+        true
+    )]
+    #[case(b"PK\x03\x04\x14\0\0\0\0\0\0\0", false)]
+    #[case(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1\0\0", true)]
+    fn test_is_complete(#[case] data: &[u8], #[case] complete: bool) {
+        assert_eq!(is_complete(data), complete);
+    }
+
+    #[rstest]
+    #[case(&["soffice", "--outdir", "{outdir}", "{input}"], true)]
+    #[case(&["convert", "--in={input}"], true)]
+    #[case(&["soffice", "--outdir", "{outdir}", "{inptu}"], false)]
+    #[case(&["soffice", "--outdir", "{outdir}"], false)]
+    #[case(&["soffice", "{input"], false)]
+    #[case(&[], false)]
+    fn test_check(#[case] command: &[&str], #[case] valid: bool) {
+        assert_eq!(check(&make_profile(command)).is_ok(), valid);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_convert() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let src = tmp.path().join("letter.docx");
+        let dst = tmp.path().join("letter.pdf");
+        fs::write(&src, b"%PDF-1.7\n").await.unwrap();
+
+        convert(
+            &make_profile(&["cp", "{input}", "{outdir}/letter.pdf"]),
+            &src,
+            &dst,
+        )
+        .await
+        .unwrap();
+        assert_eq!(fs::read(&dst).await.unwrap(), b"%PDF-1.7\n");
+    }
+
+    #[rstest]
+    #[case(&["true"])]
+    #[case(&["false"])]
+    #[case(&["cp", "{input}", "{outdir}/letter.txt"])]
+    #[tokio::test]
+    async fn test_convert_failure(#[case] command: &[&str]) {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let src = tmp.path().join("letter.docx");
+        fs::write(&src, b"PK\x03\x04").await.unwrap();
+
+        let result = convert(&make_profile(command), &src, &tmp.path().join("letter.pdf")).await;
+        assert!(matches!(result, Err(Error::OfficeConversionError(_))));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_convert_missing_command() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let result = convert(
+            &make_profile(&["mrdocument-no-such-converter", "{input}"]),
+            &tmp.path().join("letter.docx"),
+            &tmp.path().join("letter.pdf"),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::DependencyMissingError(_))));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::document::{DocumentData, MAX_SUFFIX_BYTES};
+use crate::error::Result;
+use crate::file_info::FileInfo;
+use crate::paths::Location;
+use crate::pdf::{metadata_entries, update_metadata};
+use crate::profile::Profile;
+use crate::sanitize::sanitize_component;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+async fn write_sidecar(
+    profile: &Profile,
+    document_data: &DocumentData,
+    suffix: &str,
+    data: &[u8],
+) -> Result<()> {
+    let path = profile
+        .paths
+        .make_path_with_new_filename(
+            Location::Outbox,
+            document_data.make_path(&profile.layout)?,
+            document_data.make_filename(&profile.layout, suffix)?,
+        )
+        .await?;
+    let mut out = fs::File::create(path).await?;
+    out.write_all(data).await?;
+
+    Ok(())
+}
+
+/// Files the document at `src` into the outbox according to `document_data`,
+/// along with its content, extra fields and summaries. PDFs carry their metadata
+/// themselves, for images it is written to a `.metadata.json` sidecar. The
+/// `original` of a converted document, a file name in the processed location,
+/// is filed alongside.
+pub async fn file_document(
+    profile: &Profile,
+    src: PathBuf,
+    document_data: &DocumentData,
+    original: Option<&str>,
+) -> Result<()> {
+    let file_info = FileInfo::new(src.clone())?;
+    let dst_path = profile
+        .paths
+        .make_path_with_new_filename(
+            Location::Outbox,
+            document_data.make_path(&profile.layout)?,
+            document_data.make_filename(&profile.layout, &file_info.extension())?,
+        )
+        .await?;
+    let metadata_fields: Vec<_> = profile
+        .fields
+        .iter()
+        .filter(|field| field.metadata)
+        .map(|field| field.name.clone())
+        .collect();
+    match file_info {
+        FileInfo::PDF(_) => {
+            update_metadata(src, dst_path, document_data, &metadata_fields)
+                .await
+                .map(|_| ())?;
+        }
+        _ => {
+            fs::copy(src, dst_path).await?;
+            let metadata: BTreeMap<_, _> = metadata_entries(document_data, &metadata_fields)
+                .into_iter()
+                .collect();
+            let metadata = serde_json::to_string_pretty(&metadata)?;
+            write_sidecar(profile, document_data, "metadata.json", metadata.as_bytes()).await?;
+        }
+    }
+    if let Some(original) = original {
+        let original = Path::new(original);
+        let dst_path = profile
+            .paths
+            .make_path_with_new_filename(
+                Location::Outbox,
+                document_data.make_path(&profile.layout)?,
+                document_data.make_filename(
+                    &profile.layout,
+                    &original
+                        .extension()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_lowercase(),
+                )?,
+            )
+            .await?;
+        fs::copy(
+            profile
+                .paths
+                .make_root(Location::Processed)
+                .join(original.file_name().unwrap_or_default()),
+            dst_path,
+        )
+        .await?;
+    }
+
+    if let Some(ref content) = document_data.content {
+        write_sidecar(profile, document_data, "content", content.as_bytes()).await?;
+    }
+    if !document_data.extra.is_empty() {
+        let extra = serde_json::to_string_pretty(&document_data.extra)?;
+        write_sidecar(profile, document_data, "json", extra.as_bytes()).await?;
+    }
+    write_sidecar(
+        profile,
+        document_data,
+        "summary",
+        document_data.summary.as_bytes(),
+    )
+    .await?;
+    if let (Some(ref translation), Some(ref language)) = (
+        &document_data.summary_translation,
+        &profile.language.translation,
+    ) {
+        let suffix = format!(
+            "{}.summary",
+            sanitize_component(
+                &language.to_lowercase(),
+                MAX_SUFFIX_BYTES - ".summary".len()
+            )
+        );
+        write_sidecar(profile, document_data, &suffix, translation.as_bytes()).await?;
+    }
+
+    Ok(())
+}
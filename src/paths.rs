@@ -1,5 +1,5 @@
-use crate::error::Result;
-use crate::util::make_unique_path;
+use crate::error::{Error, Result};
+use crate::util::{is_relative_below, make_unique_path};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -16,6 +16,8 @@ pub enum Location {
     Transit,
     Processed,
     Error,
+    Review,
+    Pending,
 }
 
 impl fmt::Display for Location {
@@ -37,11 +39,9 @@ impl Default for Paths {
             path: dirs::home_dir()
                 .expect("Could not determine home directory")
                 .join("MrDocument"),
-            location_mapping: HashMap::from(
-                Location::iter()
-                    .map(|location| (location, location.to_string()))
-                    .collect::<HashMap<_, _>>(),
-            ),
+            location_mapping: Location::iter()
+                .map(|location| (location, location.to_string()))
+                .collect::<HashMap<_, _>>(),
         }
     }
 }
@@ -73,6 +73,18 @@ impl Paths {
         make_unique_path(self.make_root(location), filename).await
     }
 
+    pub async fn make_path_with_new_filename(
+        &self,
+        location: Location,
+        path: PathBuf,
+        filename: String,
+    ) -> Result<PathBuf> {
+        if !is_relative_below(&path) {
+            return Err(Error::InvalidPathError(path));
+        }
+        make_unique_path(self.make_root(location).join(path), filename).await
+    }
+
     fn get_location_name(&self, location: Location) -> String {
         self.location_mapping
             .get(&location)
@@ -13,10 +13,53 @@ fn make_metdata_entry(key: String, value: String) -> Vec<String> {
     ]
 }
 
+/// The metadata entries written for `document_data`: Keywords, the validation
+/// outcome, the e-mail the document was attached to and the extra fields marked
+/// as metadata.
+pub fn metadata_entries(
+    document_data: &DocumentData,
+    metadata_fields: &[String],
+) -> Vec<(String, String)> {
+    let keywords: Vec<_> = document_data
+        .keywords
+        .iter()
+        .cloned()
+        .chain(vec![
+            document_data.class.clone(),
+            document_data.source.clone(),
+        ])
+        .collect();
+
+    let extra = metadata_fields.iter().filter_map(|name| {
+        document_data.extra.get(name).map(|value| {
+            let value = match value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            (name.clone(), value)
+        })
+    });
+
+    let email = document_data
+        .email
+        .clone()
+        .map(|email| ("Email".to_string(), email));
+
+    vec![
+        ("Keywords".to_string(), keywords.join(", ")),
+        ("Validation".to_string(), document_data.validation.summary()),
+    ]
+    .into_iter()
+    .chain(email)
+    .chain(extra)
+    .collect()
+}
+
 pub async fn update_metadata(
     src: PathBuf,
     dst: PathBuf,
     document_data: &DocumentData,
+    metadata_fields: &[String],
 ) -> Result<Vec<()>> {
     log::info!("Updating metadata {src:?}");
     let mut process_in = Command::new("pdftk")
@@ -47,25 +90,12 @@ pub async fn update_metadata(
     let mut data = String::new();
     data_in.read_to_string(&mut data).await?;
 
-    let keywords: Vec<_> = document_data
-        .keywords
-        .iter()
-        .cloned()
-        .chain(vec![
-            document_data.class.clone(),
-            document_data.source.clone(),
-        ])
-        .collect();
-
     let updated = data
-        + &vec![make_metdata_entry(
-            "Keywords".to_string(),
-            keywords.join(", "),
-        )]
-        .into_iter()
-        .flatten()
-        .collect::<Vec<_>>()
-        .join("\n");
+        + &metadata_entries(document_data, metadata_fields)
+            .into_iter()
+            .flat_map(|(key, value)| make_metdata_entry(key, value))
+            .collect::<Vec<_>>()
+            .join("\n");
 
     log::debug!("writing data");
     data_out.write_all(updated.as_bytes()).await?;
@@ -100,7 +130,9 @@ pub async fn update_metadata(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::validation::ValidationOutcome;
     use rstest::rstest;
+    use std::collections::BTreeMap;
     use tempdir::TempDir;
 
     #[rstest]
@@ -110,16 +142,22 @@ mod tests {
         let document_data = DocumentData {
             title: "This Title".to_string(),
             summary: "This summary".to_string(),
+            summary_translation: None,
             class: "This class".to_string(),
             source: "This source".to_string(),
             date: "2024-11-11".to_string(),
             keywords: vec!["key1".to_string(), "key2".to_string(), "foo".to_string()],
             content: Some("foobar".to_string()),
+            extra: BTreeMap::from([("invoice_total".to_string(), serde_json::json!(42.5))]),
+            email: None,
+            validation: ValidationOutcome::default(),
+            confidence: BTreeMap::new(),
         };
         update_metadata(
             PathBuf::from("files/example.pdf"),
             tmp.path().join("example-mod.pdf"),
             &document_data,
+            &["invoice_total".to_string()],
         )
         .await
         .unwrap();
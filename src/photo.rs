@@ -0,0 +1,488 @@
+use crate::error::{Error, Result};
+use crate::file_type::FileType;
+use crate::profile::PageProfile;
+use base64::{engine::general_purpose, Engine as _};
+use image::codecs::tiff::TiffDecoder;
+use image::error::{DecodingError, ImageFormatHint};
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, Stream};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+use tokio::fs;
+use tokio::process::Command;
+use which::which;
+
+/// Images larger than this are downscaled unless `max_dimension` is configured.
+pub const DEFAULT_MAX_DIMENSION: u32 = 2048;
+
+/// Nominal resolution of images in generated PDFs. It matches the default resolution
+/// of `pdftoppm`, so pages are rendered at the original size of their image.
+const PDF_DPI: f32 = 150.0;
+
+fn decode(mut decoder: impl ImageDecoder) -> Result<DynamicImage> {
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    Ok(image)
+}
+
+fn tiff_error(err: tiff::TiffError) -> Error {
+    Error::ImageError(ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Tiff),
+        err,
+    )))
+}
+
+/// Returns the directory offsets of all frames of the TIFF `data`.
+fn tiff_frames(data: &[u8]) -> Result<Vec<u64>> {
+    let mut decoder = tiff::decoder::Decoder::new(Cursor::new(data)).map_err(tiff_error)?;
+    let mut offsets = Vec::new();
+    loop {
+        offsets.extend(decoder.ifd_pointer().map(|pointer| pointer.0));
+        if !decoder.more_images() {
+            break;
+        }
+        decoder.next_image().map_err(tiff_error)?;
+    }
+
+    Ok(offsets)
+}
+
+/// Returns a copy of the TIFF `data` whose first directory is the one at `offset`.
+/// Image decoders only read the first frame of a TIFF, so this selects the frame they read.
+fn select_tiff_frame(data: &[u8], offset: u64) -> Vec<u8> {
+    let mut data = data.to_vec();
+    let big_endian = data.starts_with(b"MM");
+    let big_tiff = data[2..4] == [0, 43] || data[2..4] == [43, 0];
+    match (big_tiff, big_endian) {
+        (false, false) => data[4..8].copy_from_slice(&(offset as u32).to_le_bytes()),
+        (false, true) => data[4..8].copy_from_slice(&(offset as u32).to_be_bytes()),
+        (true, false) => data[8..16].copy_from_slice(&offset.to_le_bytes()),
+        (true, true) => data[8..16].copy_from_slice(&offset.to_be_bytes()),
+    }
+    data
+}
+
+/// Returns the number of frames of the image `data`. Only TIFFs can have more than one.
+pub fn frame_count(data: &[u8]) -> Result<u32> {
+    match FileType::detect(data) {
+        FileType::Tiff => Ok(tiff_frames(data)?.len() as u32),
+        _ => Ok(1),
+    }
+}
+
+/// Decodes frame `index` of the image `data` and rotates it upright according to its orientation.
+pub fn decode_frame(data: &[u8], index: u32) -> Result<DynamicImage> {
+    match FileType::detect(data) {
+        FileType::Tiff => {
+            let offset = *tiff_frames(data)?
+                .get(index as usize)
+                .ok_or_else(|| Error::Other(format!("TIFF has no frame {index}")))?;
+            decode(TiffDecoder::new(Cursor::new(select_tiff_frame(
+                data, offset,
+            )))?)
+        }
+        _ => decode(
+            ImageReader::new(Cursor::new(data))
+                .with_guessed_format()?
+                .into_decoder()?,
+        ),
+    }
+}
+
+/// Finds the program to convert HEIC images with: `heif-dec`, `heif-convert` or `sips`.
+pub fn heic_converter() -> Result<PathBuf> {
+    which("heif-dec")
+        .or_else(|_| which("heif-convert"))
+        .or_else(|_| which("sips"))
+        .map_err(|_| Error::DependencyMissingError("heif-convert".to_string()))
+}
+
+/// Converts the HEIC image at `path` to PNG, see `heic_converter`.
+pub async fn convert_heic(path: &Path) -> Result<Vec<u8>> {
+    let tmp_dir = TempDir::new("mrdocument")?;
+    let png_path = tmp_dir.path().join("image.png");
+    let program = heic_converter()?;
+    let mut command = Command::new(&program);
+    if program.file_name().is_some_and(|name| name == "sips") {
+        command
+            .args(["-s", "format", "png"])
+            .arg(path)
+            .arg("--out")
+            .arg(&png_path);
+    } else {
+        command.arg(path).arg(&png_path);
+    }
+    let output = command.output().await?;
+    if !output.status.success() {
+        return Err(Error::ImageConversionError(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+
+    Ok(fs::read(&png_path).await?)
+}
+
+/// Prepares the image for upload: Downscaled to the configured maximum dimension,
+/// converted to grayscale if configured and encoded in `format`.
+pub fn prepare(image: DynamicImage, profile: &PageProfile, format: ImageFormat) -> Result<Vec<u8>> {
+    let max_dimension = profile.max_dimension.unwrap_or(DEFAULT_MAX_DIMENSION);
+    let image = if image.width() > max_dimension || image.height() > max_dimension {
+        image.resize(max_dimension, max_dimension, FilterType::Lanczos3)
+    } else {
+        image
+    };
+    let image = if profile.grayscale {
+        image.grayscale()
+    } else {
+        image
+    };
+    let image = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.into_rgb8()),
+        _ => image,
+    };
+
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), format)?;
+
+    Ok(data)
+}
+
+/// Decodes and prepares the given frames (counting from 1) of the image `data`,
+/// returning them base64 encoded.
+pub async fn process(
+    data: Vec<u8>,
+    frames: Vec<u32>,
+    profile: &PageProfile,
+    format: ImageFormat,
+) -> Result<Vec<String>> {
+    let profile = profile.clone();
+    tokio::task::spawn_blocking(move || {
+        frames
+            .into_iter()
+            .map(|frame| {
+                let prepared = prepare(decode_frame(&data, frame - 1)?, &profile, format)?;
+                log::info!("Prepared frame {frame} ({} bytes)", prepared.len());
+                Ok(general_purpose::STANDARD.encode(prepared))
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| Error::UnexpectedError(e.to_string()))?
+}
+
+/// Checks that the image `data` can be decoded completely.
+pub async fn check(data: Vec<u8>) -> Result<()> {
+    tokio::task::spawn_blocking(move || {
+        let last = frame_count(&data)?.saturating_sub(1);
+        decode_frame(&data, last).map(|_| ())
+    })
+    .await
+    .map_err(|e| Error::UnexpectedError(e.to_string()))?
+}
+
+struct PdfImage {
+    width: u32,
+    height: u32,
+    rotate: i64,
+    stream: Stream,
+}
+
+/// Returns the number of colour components of the JPEG `data` from its frame header.
+fn jpeg_components(data: &[u8]) -> Option<u8> {
+    let mut i = 2;
+    while i + 9 < data.len() && data[i] == 0xFF {
+        let marker = data[i + 1];
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            return Some(data[i + 9]);
+        }
+        i += 2 + u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+    }
+    None
+}
+
+fn make_raw_pdf_image(image: DynamicImage) -> Result<PdfImage> {
+    let (color_space, pixels) = if image.color().has_color() {
+        ("DeviceRGB", image.to_rgb8().into_raw())
+    } else {
+        ("DeviceGray", image.to_luma8().into_raw())
+    };
+    let mut stream = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => image.width(),
+            "Height" => image.height(),
+            "ColorSpace" => color_space,
+            "BitsPerComponent" => 8,
+        },
+        pixels,
+    );
+    stream
+        .compress()
+        .map_err(|e| Error::PdfConversionError(e.to_string()))?;
+
+    Ok(PdfImage {
+        width: image.width(),
+        height: image.height(),
+        rotate: 0,
+        stream,
+    })
+}
+
+/// Creates the image XObjects for the frames of the image `data`. JPEGs are embedded
+/// as they are, with their EXIF orientation turned into a page rotation where possible.
+/// Other images are decoded, rotated upright and embedded losslessly.
+fn make_pdf_images(data: Vec<u8>) -> Result<Vec<PdfImage>> {
+    if FileType::detect(&data) != FileType::Jpeg {
+        return (0..frame_count(&data)?)
+            .map(|frame| make_raw_pdf_image(decode_frame(&data, frame)?))
+            .collect();
+    }
+
+    let (orientation, (width, height)) = {
+        let mut decoder = ImageReader::new(Cursor::new(&data))
+            .with_guessed_format()?
+            .into_decoder()?;
+        (decoder.orientation()?, decoder.dimensions())
+    };
+    let rotate = match orientation {
+        Orientation::NoTransforms => Some(0),
+        Orientation::Rotate90 => Some(90),
+        Orientation::Rotate180 => Some(180),
+        Orientation::Rotate270 => Some(270),
+        _ => None,
+    };
+    match (rotate, jpeg_components(&data)) {
+        (Some(rotate), Some(components @ (1 | 3))) => {
+            let color_space = if components == 1 {
+                "DeviceGray"
+            } else {
+                "DeviceRGB"
+            };
+            Ok(vec![PdfImage {
+                width,
+                height,
+                rotate,
+                stream: Stream::new(
+                    dictionary! {
+                        "Type" => "XObject",
+                        "Subtype" => "Image",
+                        "Width" => width,
+                        "Height" => height,
+                        "ColorSpace" => color_space,
+                        "BitsPerComponent" => 8,
+                        "Filter" => "DCTDecode",
+                    },
+                    data,
+                ),
+            }])
+        }
+        _ => Ok(vec![make_raw_pdf_image(decode_frame(&data, 0)?)?]),
+    }
+}
+
+/// Creates a PDF with one page per image, or per frame of a multi-page image,
+/// each at its original resolution.
+pub fn make_pdf(images: Vec<Vec<u8>>) -> Result<Vec<u8>> {
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+    let mut kids: Vec<Object> = Vec::new();
+    for image in images
+        .into_iter()
+        .map(make_pdf_images)
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+    {
+        let width = image.width as f32 * 72.0 / PDF_DPI;
+        let height = image.height as f32 * 72.0 / PDF_DPI;
+        let image_id = document.add_object(image.stream);
+        let content = Content {
+            operations: vec![
+                Operation::new("q", vec![]),
+                Operation::new(
+                    "cm",
+                    vec![
+                        width.into(),
+                        0.into(),
+                        0.into(),
+                        height.into(),
+                        0.into(),
+                        0.into(),
+                    ],
+                ),
+                Operation::new("Do", vec!["Im0".into()]),
+                Operation::new("Q", vec![]),
+            ],
+        };
+        let content_id = document.add_object(Stream::new(
+            dictionary! {},
+            content
+                .encode()
+                .map_err(|e| Error::PdfConversionError(e.to_string()))?,
+        ));
+        kids.push(
+            document
+                .add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+                    "Rotate" => image.rotate,
+                    "Contents" => content_id,
+                    "Resources" => dictionary! {
+                        "XObject" => dictionary! { "Im0" => image_id },
+                    },
+                })
+                .into(),
+        );
+    }
+    let count = kids.len() as i64;
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+        }),
+    );
+    let catalog_id = document.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    document.trailer.set("Root", catalog_id);
+
+    let mut data = Vec::new();
+    document
+        .save_to(&mut data)
+        .map_err(|e| Error::PdfConversionError(e.to_string()))?;
+
+    Ok(data)
+}
+
+/// Writes a PDF with one page per image to `dst`.
+pub async fn convert_to_pdf(images: Vec<Vec<u8>>, dst: &Path) -> Result<()> {
+    let data = tokio::task::spawn_blocking(move || make_pdf(images))
+        .await
+        .map_err(|e| Error::UnexpectedError(e.to_string()))??;
+    fs::write(dst, data).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{ImageEncoder, RgbImage};
+    use rstest::rstest;
+
+    fn make_exif(orientation: u16) -> Vec<u8> {
+        let mut exif = b"II*\0".to_vec();
+        exif.extend(8u32.to_le_bytes());
+        exif.extend(1u16.to_le_bytes());
+        exif.extend(0x0112u16.to_le_bytes());
+        exif.extend(3u16.to_le_bytes());
+        exif.extend(1u32.to_le_bytes());
+        exif.extend(orientation.to_le_bytes());
+        exif.extend([0, 0]);
+        exif.extend(0u32.to_le_bytes());
+        exif
+    }
+
+    fn make_jpeg(width: u32, height: u32, orientation: u16) -> Vec<u8> {
+        let image = RgbImage::new(width, height);
+        let mut data = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut data);
+        encoder.set_exif_metadata(make_exif(orientation)).unwrap();
+        encoder
+            .write_image(&image, width, height, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        data
+    }
+
+    fn make_png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn make_tiff(frames: &[(u32, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = tiff::encoder::TiffEncoder::new(Cursor::new(&mut data)).unwrap();
+        for (width, height) in frames {
+            let pixels = vec![0u8; (width * height) as usize];
+            encoder
+                .write_image::<tiff::encoder::colortype::Gray8>(*width, *height, &pixels)
+                .unwrap();
+        }
+        data
+    }
+
+    #[rstest]
+    #[case(1, (40, 30))]
+    #[case(6, (30, 40))]
+    #[case(8, (30, 40))]
+    #[case(3, (40, 30))]
+    fn test_decode_orientation(#[case] orientation: u16, #[case] dimensions: (u32, u32)) {
+        let image = decode_frame(&make_jpeg(40, 30, orientation), 0).unwrap();
+        assert_eq!((image.width(), image.height()), dimensions);
+    }
+
+    #[rstest]
+    fn test_tiff_frames() {
+        let data = make_tiff(&[(20, 10), (30, 40), (50, 60)]);
+        assert_eq!(frame_count(&data).unwrap(), 3);
+        let image = decode_frame(&data, 1).unwrap();
+        assert_eq!((image.width(), image.height()), (30, 40));
+        let image = decode_frame(&data, 2).unwrap();
+        assert_eq!((image.width(), image.height()), (50, 60));
+        assert!(decode_frame(&data, 3).is_err());
+        assert_eq!(frame_count(&make_png(10, 10)).unwrap(), 1);
+    }
+
+    #[rstest]
+    fn test_make_pdf() {
+        let images = vec![
+            make_jpeg(40, 30, 6),
+            make_png(30, 60),
+            make_tiff(&[(20, 10), (30, 40)]),
+        ];
+        let document = Document::load_mem(&make_pdf(images).unwrap()).unwrap();
+        let pages = document.get_pages();
+        assert_eq!(pages.len(), 4);
+
+        let first = document.get_dictionary(pages[&1]).unwrap();
+        assert_eq!(first.get(b"Rotate").unwrap().as_i64().unwrap(), 90);
+        let second = document.get_dictionary(pages[&2]).unwrap();
+        assert_eq!(second.get(b"Rotate").unwrap().as_i64().unwrap(), 0);
+        let media_box = second.get(b"MediaBox").unwrap().as_array().unwrap();
+        assert_eq!(media_box[2].as_float().unwrap(), 30.0 * 72.0 / PDF_DPI);
+    }
+
+    #[rstest]
+    #[case(None, (2048, 256))]
+    #[case(Some(1024), (1024, 128))]
+    #[case(Some(5000), (4096, 512))]
+    fn test_prepare_downscale(#[case] max_dimension: Option<u32>, #[case] dimensions: (u32, u32)) {
+        let profile = PageProfile {
+            max_dimension,
+            ..PageProfile::default()
+        };
+        let data = prepare(
+            DynamicImage::ImageRgb8(RgbImage::new(4096, 512)),
+            &profile,
+            ImageFormat::Jpeg,
+        )
+        .unwrap();
+        let image = image::load_from_memory(&data).unwrap();
+        assert_eq!((image.width(), image.height()), dimensions);
+    }
+}
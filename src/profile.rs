@@ -1,8 +1,13 @@
+use crate::alias::CompiledAliases;
+use crate::document::CONFIDENCE_FIELDS;
 use crate::error::{Error, Result};
+use crate::office;
 use crate::paths::Paths;
+use crate::template;
 use openai_api_rs::v1::common::GPT4_O;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::io::ErrorKind;
 use std::path::PathBuf;
 use tokio::fs;
@@ -20,6 +25,16 @@ fn default_temperature() -> u8 {
     100
 }
 
+/// OpenAI accepts temperatures up to 2.0, Anthropic up to 1.0.
+const MAX_TEMPERATURE: u8 = 200;
+
+impl ChatGptProfile {
+    /// The temperature as sent to the API, `temperature` is given in hundredths.
+    pub fn api_temperature(&self) -> f64 {
+        f64::from(self.temperature) / 100.0
+    }
+}
+
 impl Default for ChatGptProfile {
     fn default() -> ChatGptProfile {
         ChatGptProfile {
@@ -30,11 +45,501 @@ impl Default for ChatGptProfile {
     }
 }
 
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct OpenAiBackend {
+    #[serde(default)]
+    pub api_base: Option<String>,
+    #[serde(default)]
+    pub no_api_key: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct AnthropicBackend {
+    #[serde(default)]
+    pub api_base: Option<String>,
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: u32,
+}
+
+fn default_max_tokens() -> u32 {
+    8192
+}
+
+impl Default for AnthropicBackend {
+    fn default() -> AnthropicBackend {
+        AnthropicBackend {
+            api_base: None,
+            max_tokens: default_max_tokens(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct TesseractBackend {
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub classifier: Option<Box<BackendProfile>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BackendProfile {
+    OpenAi(OpenAiBackend),
+    Anthropic(AnthropicBackend),
+    Tesseract(TesseractBackend),
+}
+
+impl Default for BackendProfile {
+    fn default() -> BackendProfile {
+        BackendProfile::OpenAi(OpenAiBackend::default())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl ImageFormat {
+    pub fn mime_type(&self) -> String {
+        format!("image/{self}")
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PageSelection {
+    #[default]
+    First,
+    FirstAndLast,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct PageProfile {
+    #[serde(default = "default_text_layer")]
+    pub text_layer: bool,
+    #[serde(default = "default_min_text_length")]
+    pub min_text_length: usize,
+    #[serde(default)]
+    pub dpi: Option<u32>,
+    #[serde(default)]
+    pub grayscale: bool,
+    #[serde(default)]
+    pub format: ImageFormat,
+    #[serde(default)]
+    pub max_dimension: Option<u32>,
+    #[serde(default)]
+    pub max_pages: Option<u32>,
+    #[serde(default)]
+    pub page_selection: PageSelection,
+}
+
+fn default_text_layer() -> bool {
+    true
+}
+
+fn default_min_text_length() -> usize {
+    100
+}
+
+impl Default for PageProfile {
+    fn default() -> PageProfile {
+        PageProfile {
+            text_layer: default_text_layer(),
+            min_text_length: default_min_text_length(),
+            dpi: None,
+            grayscale: false,
+            format: ImageFormat::default(),
+            max_dimension: None,
+            max_pages: None,
+            page_selection: PageSelection::default(),
+        }
+    }
+}
+
+impl PageProfile {
+    pub fn select_pages(&self, page_count: u32) -> Vec<u32> {
+        match self.max_pages {
+            Some(max_pages) if max_pages < page_count => match self.page_selection {
+                PageSelection::First => (1..=max_pages).collect(),
+                PageSelection::FirstAndLast => {
+                    let first = max_pages.div_ceil(2);
+                    let last = max_pages - first;
+                    (1..=first)
+                        .chain(page_count - last + 1..=page_count)
+                        .collect()
+                }
+            },
+            _ => (1..=page_count).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ChunkingProfile {
+    pub batch_size: usize,
+    #[serde(default = "default_batch_retries")]
+    pub retries: u32,
+}
+
+fn default_batch_retries() -> u32 {
+    2
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct RetryProfile {
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_initial_delay")]
+    pub initial_delay: u64,
+    #[serde(default = "default_max_delay")]
+    pub max_delay: u64,
+    #[serde(default = "default_jitter")]
+    pub jitter: bool,
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_initial_delay() -> u64 {
+    2
+}
+
+fn default_max_delay() -> u64 {
+    300
+}
+
+fn default_jitter() -> bool {
+    true
+}
+
+impl Default for RetryProfile {
+    fn default() -> RetryProfile {
+        RetryProfile {
+            max_attempts: default_max_attempts(),
+            initial_delay: default_initial_delay(),
+            max_delay: default_max_delay(),
+            jitter: default_jitter(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct RequeueProfile {
+    #[serde(default = "default_requeue_delay")]
+    pub delay: u64,
+    #[serde(default = "default_requeue_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_requeue_delay() -> u64 {
+    3600
+}
+
+fn default_requeue_max_attempts() -> u32 {
+    3
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Date,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct FieldProfile {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub metadata: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct TaxonomyEntry {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct TaxonomyProfile {
+    #[serde(default)]
+    pub classes: Vec<TaxonomyEntry>,
+    #[serde(default)]
+    pub sources: Vec<TaxonomyEntry>,
+    #[serde(default)]
+    pub other: bool,
+}
+
+pub const OTHER: &str = "other";
+
+impl TaxonomyProfile {
+    fn allowed(&self, entries: &[TaxonomyEntry]) -> Option<Vec<String>> {
+        if entries.is_empty() {
+            return None;
+        }
+        Some(
+            entries
+                .iter()
+                .map(|entry| entry.name.clone())
+                .chain(self.other.then(|| OTHER.to_string()))
+                .collect(),
+        )
+    }
+
+    pub fn allowed_classes(&self) -> Option<Vec<String>> {
+        self.allowed(&self.classes)
+    }
+
+    pub fn allowed_sources(&self) -> Option<Vec<String>> {
+        self.allowed(&self.sources)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct AliasRule {
+    pub canonical: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+    #[serde(skip)]
+    pub compiled: CompiledAliases,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct AliasProfile {
+    #[serde(default)]
+    pub classes: Vec<AliasRule>,
+    #[serde(default)]
+    pub sources: Vec<AliasRule>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct LayoutProfile {
+    #[serde(default = "default_layout_path")]
+    pub path: String,
+    #[serde(default = "default_layout_filename")]
+    pub filename: String,
+}
+
+fn default_layout_path() -> String {
+    "{class|lower}/{source|lower}".to_string()
+}
+
+fn default_layout_filename() -> String {
+    "{date}-{title}".to_string()
+}
+
+impl Default for LayoutProfile {
+    fn default() -> LayoutProfile {
+        LayoutProfile {
+            path: default_layout_path(),
+            filename: default_layout_filename(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ImageProfile {
+    #[serde(default)]
+    pub pdf: bool,
+    #[serde(default)]
+    pub group_seconds: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct OfficeProfile {
+    #[serde(default = "default_office_command")]
+    pub command: Vec<String>,
+    #[serde(default = "default_office_timeout")]
+    pub timeout: u64,
+    #[serde(default)]
+    pub keep_original: bool,
+}
+
+fn default_office_command() -> Vec<String> {
+    [
+        "soffice",
+        "--headless",
+        "--convert-to",
+        "pdf",
+        "--outdir",
+        "{outdir}",
+        "{input}",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+fn default_office_timeout() -> u64 {
+    120
+}
+
+impl Default for OfficeProfile {
+    fn default() -> OfficeProfile {
+        OfficeProfile {
+            command: default_office_command(),
+            timeout: default_office_timeout(),
+            keep_original: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ReviewProfile {
+    #[serde(default = "default_review_threshold")]
+    pub threshold: u8,
+    #[serde(default = "default_review_fields")]
+    pub fields: Vec<String>,
+}
+
+fn default_review_threshold() -> u8 {
+    70
+}
+
+fn default_review_fields() -> Vec<String> {
+    vec![
+        "class".to_string(),
+        "source".to_string(),
+        "title".to_string(),
+        "date".to_string(),
+    ]
+}
+
+impl Default for ReviewProfile {
+    fn default() -> ReviewProfile {
+        ReviewProfile {
+            threshold: default_review_threshold(),
+            fields: default_review_fields(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct LanguageProfile {
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub keywords: Option<String>,
+    #[serde(default)]
+    pub translation: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct HintsProfile {
+    #[serde(default)]
+    pub filename: bool,
+    #[serde(default)]
+    pub mtime: bool,
+    #[serde(default)]
+    pub date_fallback: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct PromptProfile {
+    #[serde(default)]
+    pub task: Option<PathBuf>,
+    #[serde(default)]
+    pub outputs: Option<PathBuf>,
+    #[serde(default)]
+    pub specs: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ValidationProfile {
+    #[serde(default = "default_repair_rounds")]
+    pub repair_rounds: u32,
+    #[serde(default = "default_max_keywords")]
+    pub max_keywords: usize,
+}
+
+fn default_repair_rounds() -> u32 {
+    2
+}
+
+fn default_max_keywords() -> usize {
+    10
+}
+
+impl Default for ValidationProfile {
+    fn default() -> ValidationProfile {
+        ValidationProfile {
+            repair_rounds: default_repair_rounds(),
+            max_keywords: default_max_keywords(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 pub struct Profile {
     pub name: String,
     #[serde(default)]
+    pub backend: BackendProfile,
+    #[serde(default)]
     pub chatgpt: ChatGptProfile,
+    #[serde(default)]
+    pub pages: PageProfile,
+    #[serde(default)]
+    pub images: ImageProfile,
+    #[serde(default)]
+    pub office: Option<OfficeProfile>,
+    #[serde(default)]
+    pub chunking: Option<ChunkingProfile>,
+    #[serde(default)]
+    pub retry: RetryProfile,
+    #[serde(default)]
+    pub requeue: Option<RequeueProfile>,
+    #[serde(default)]
+    pub fields: Vec<FieldProfile>,
+    #[serde(default)]
+    pub taxonomy: TaxonomyProfile,
+    #[serde(default)]
+    pub aliases: AliasProfile,
+    #[serde(default)]
+    pub layout: LayoutProfile,
+    #[serde(default)]
+    pub prompt: PromptProfile,
+    #[serde(default)]
+    pub hints: HintsProfile,
+    #[serde(default)]
+    pub language: LanguageProfile,
+    #[serde(default)]
+    pub review: Option<ReviewProfile>,
+    #[serde(default)]
+    pub approval: bool,
+    #[serde(default)]
+    pub validation: ValidationProfile,
     pub paths: Paths,
     #[serde(default)]
     pub polling: bool,
@@ -44,7 +549,24 @@ impl Default for Profile {
     fn default() -> Profile {
         Profile {
             name: "default".to_string(),
+            backend: BackendProfile::default(),
             chatgpt: ChatGptProfile::default(),
+            pages: PageProfile::default(),
+            images: ImageProfile::default(),
+            office: None,
+            chunking: None,
+            retry: RetryProfile::default(),
+            requeue: None,
+            fields: Vec::new(),
+            taxonomy: TaxonomyProfile::default(),
+            aliases: AliasProfile::default(),
+            layout: LayoutProfile::default(),
+            prompt: PromptProfile::default(),
+            hints: HintsProfile::default(),
+            language: LanguageProfile::default(),
+            review: None,
+            approval: false,
+            validation: ValidationProfile::default(),
             paths: Paths::default(),
             polling: false,
         }
@@ -53,7 +575,58 @@ impl Default for Profile {
 
 impl Profile {
     pub async fn new_from_file(path: PathBuf) -> Result<Profile> {
-        Ok(serde_yaml::from_str(&fs::read_to_string(path).await?)?)
+        let profile: Profile = serde_yaml::from_str(&fs::read_to_string(path).await?)?;
+        profile.check()?;
+
+        Ok(profile)
+    }
+
+    /// Checks the settings that depend on each other, which the schema cannot express.
+    pub fn check(&self) -> Result<()> {
+        let is_known = |name: &str| {
+            template::VARIABLES.contains(&name)
+                || self.fields.iter().any(|field| field.name == name)
+        };
+        template::check_with(&self.layout.path, is_known)?;
+        template::check_with(&self.layout.filename, is_known)?;
+        self.aliases.check()?;
+        if self.chatgpt.temperature > MAX_TEMPERATURE {
+            return Err(Error::Other(format!(
+                "`chatgpt.temperature` must be between 0 and {MAX_TEMPERATURE}: {}",
+                self.chatgpt.temperature
+            )));
+        }
+        if self.pages.max_pages == Some(0) {
+            return Err(Error::Other(
+                "`pages.max_pages` must be at least 1, omit it to send all pages".to_string(),
+            ));
+        }
+        if self
+            .chunking
+            .as_ref()
+            .is_some_and(|chunking| chunking.batch_size == 0)
+        {
+            return Err(Error::Other(
+                "`chunking.batch_size` must be at least 1".to_string(),
+            ));
+        }
+        if let Some(ref office) = self.office {
+            office::check(office)?;
+        }
+        if let Some(ref review) = self.review {
+            if let Some(field) = review
+                .fields
+                .iter()
+                .find(|field| !CONFIDENCE_FIELDS.contains(&field.as_str()))
+            {
+                return Err(Error::Other(format!(
+                    "Unknown review field {field:?}, expected one of {}",
+                    CONFIDENCE_FIELDS.join(", ")
+                )));
+            }
+        }
+
+        Ok(())
     }
 
     pub fn with_path(self, path: PathBuf) -> Profile {
@@ -99,3 +672,102 @@ impl Profile {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        "name: foo\npaths:\n  path: /home/foo\n  locations: {}\n",
+        BackendProfile::default()
+    )]
+    #[case(
+        "name: foo\nbackend:\n  type: openai\npaths:\n  path: /home/foo\n  locations: {}\n",
+        BackendProfile::default()
+    )]
+    #[case(
+        "name: foo\nbackend:\n  type: openai\n  api_base: http://localhost:11434/v1\n  no_api_key: true\npaths:\n  path: /home/foo\n  locations: {}\n",
+        BackendProfile::OpenAi(OpenAiBackend {
+            api_base: Some("http://localhost:11434/v1".to_string()),
+            no_api_key: true,
+        })
+    )]
+    #[case(
+        "name: foo\nbackend:\n  type: anthropic\npaths:\n  path: /home/foo\n  locations: {}\n",
+        BackendProfile::Anthropic(AnthropicBackend::default())
+    )]
+    #[case(
+        "name: foo\nbackend:\n  type: tesseract\n  language: deu+eng\n  classifier:\n    type: openai\n    api_base: http://localhost:8080/v1\npaths:\n  path: /home/foo\n  locations: {}\n",
+        BackendProfile::Tesseract(TesseractBackend {
+            language: Some("deu+eng".to_string()),
+            classifier: Some(Box::new(BackendProfile::OpenAi(OpenAiBackend {
+                api_base: Some("http://localhost:8080/v1".to_string()),
+                no_api_key: false,
+            }))),
+        })
+    )]
+    fn test_backend(#[case] yaml: &str, #[case] backend: BackendProfile) {
+        let profile: Profile = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(profile.backend, backend);
+    }
+
+    #[rstest]
+    #[case(None, PageSelection::First, 5, vec![1, 2, 3, 4, 5])]
+    #[case(Some(3), PageSelection::First, 5, vec![1, 2, 3])]
+    #[case(Some(3), PageSelection::FirstAndLast, 10, vec![1, 2, 10])]
+    #[case(Some(4), PageSelection::FirstAndLast, 10, vec![1, 2, 9, 10])]
+    #[case(Some(4), PageSelection::FirstAndLast, 3, vec![1, 2, 3])]
+    fn test_select_pages(
+        #[case] max_pages: Option<u32>,
+        #[case] page_selection: PageSelection,
+        #[case] page_count: u32,
+        #[case] pages: Vec<u32>,
+    ) {
+        let profile = PageProfile {
+            max_pages,
+            page_selection,
+            ..PageProfile::default()
+        };
+        assert_eq!(profile.select_pages(page_count), pages);
+    }
+
+    #[rstest]
+    #[case("layout:\n  filename: \"{date}-{title}\"\n", true)]
+    #[case("layout:\n  filename: \"{date}-{invoice_total}\"\n", true)]
+    #[case("layout:\n  filename: \"{date}-{tilte}\"\n", false)]
+    #[case("layout:\n  path: \"{year}/{clas}\"\n", false)]
+    #[case("layout:\n  path: \"{class\"\n", false)]
+    #[case("review:\n  fields: [class, keywords]\n", true)]
+    #[case("review:\n  fields: [class, titel]\n", false)]
+    #[case(
+        "aliases:\n  sources:\n    - canonical: telekom\n      patterns: ['^telekom-']\n",
+        true
+    )]
+    #[case(
+        "aliases:\n  sources:\n    - canonical: telekom\n      patterns: ['^telekom-(']\n",
+        false
+    )]
+    #[case("pages:\n  max_pages: 1\n", true)]
+    #[case("pages:\n  max_pages: 0\n", false)]
+    #[case("office:\n  command: [soffice, \"{outdir}\", \"{input}\"]\n", true)]
+    #[case("office:\n  command: [soffice, \"{outdir}\", \"{inptu}\"]\n", false)]
+    #[case("chunking:\n  batch_size: 1\n", true)]
+    #[case("chunking:\n  batch_size: 0\n", false)]
+    #[case(
+        "chatgpt:\n  model: gpt-4o\n  temperature: 200\n  additional_instructions: []\n",
+        true
+    )]
+    #[case(
+        "chatgpt:\n  model: gpt-4o\n  temperature: 255\n  additional_instructions: []\n",
+        false
+    )]
+    fn test_check(#[case] yaml: &str, #[case] valid: bool) {
+        let yaml = format!(
+            "name: foo\nfields:\n  - name: invoice_total\n    type: number\n{yaml}paths:\n  path: /home/foo\n  locations: {{}}\n"
+        );
+        let profile: Profile = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(profile.check().is_ok(), valid);
+    }
+}
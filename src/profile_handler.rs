@@ -1,3 +1,4 @@
+use crate::analyzer::Analyzer;
 use crate::error::Result;
 use crate::handler::{EventHandler, Handler};
 use crate::paths::Location;
@@ -42,7 +43,8 @@ impl ProfileHandler {
         let profile = Profile::new_from_file(path.clone()).await?;
         log::info!("Starting watcher on {:?}", profile.paths.path);
         let inbox_path = profile.paths.make_root(Location::Inbox);
-        let handler = Handler::new(profile.clone(), 1).await?;
+        let analyzer = Analyzer::new(&profile)?;
+        let handler = Handler::new(profile.clone(), analyzer, 1).await?;
         let watcher_loop = WatcherLoop::new(inbox_path, handler, profile.polling).await?;
 
         Ok((hash, watcher_loop))
@@ -88,16 +90,13 @@ impl EventHandler for ProfileHandler {
     }
 
     async fn handle_event(&mut self, event: Event) {
-        match event {
-            Event { kind, paths, .. } => {
-                for path in paths {
-                    self.handle_profile(path.clone(), kind)
-                        .await
-                        .inspect_err(|e| log::error!("Unable to run profile: {path:?}: {e:?}"))
-                        .ok();
-                }
-            }
-        };
+        let Event { kind, paths, .. } = event;
+        for path in paths {
+            self.handle_profile(path.clone(), kind)
+                .await
+                .inspect_err(|e| log::error!("Unable to run profile: {path:?}: {e:?}"))
+                .ok();
+        }
     }
 }
 
@@ -0,0 +1,521 @@
+use crate::analyzer::DocumentContext;
+use crate::error::{Error, Result};
+use crate::profile::{
+    FieldProfile, FieldType, HintsProfile, LanguageProfile, Profile, ReviewProfile, TaxonomyEntry,
+    TaxonomyProfile, OTHER,
+};
+use crate::template;
+use serde::Deserialize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+pub const DOCUMENT_DATA_FUNCTION: &str = "return_document_data";
+pub const TRANSCRIPTION_FUNCTION: &str = "return_transcription";
+
+pub const TRANSCRIPTION_PROMPT: &str = "You will be given pages of a document, either as scanned pages or as text extracted from them. Transcribe the complete text of all pages in their order. Do not summarize, shorten or omit anything, and do not add any comments of your own.";
+
+/// The arguments of the transcription function.
+#[derive(Debug, Deserialize)]
+pub struct Transcription {
+    pub content: String,
+}
+
+const TASK: &str = "You will be given a document, either as scanned pages or as text extracted from its pages. It may consist of one or more pages. You shall provide as output:";
+
+#[derive(Clone, Debug, Default)]
+pub struct Prompt {
+    fields: Vec<FieldProfile>,
+    taxonomy: TaxonomyProfile,
+    hints: HintsProfile,
+    language: LanguageProfile,
+    review: Option<ReviewProfile>,
+    task: Option<String>,
+    outputs: Option<String>,
+    specs: Option<String>,
+}
+
+/// The variables available in prompt templates, see `render_section`.
+const SECTION_VARIABLES: &[&str] = &["default", "classes", "sources", "date", "filename"];
+
+fn load_template(dir: &Path, path: &Option<PathBuf>) -> Result<Option<String>> {
+    path.as_ref()
+        .map(|path| {
+            let path = dir.join(path);
+            let template = std::fs::read_to_string(&path).map_err(|e| {
+                Error::TemplateError(format!("Cannot read prompt template {path:?}: {e}"))
+            })?;
+            template::check_with(&template, |name| SECTION_VARIABLES.contains(&name))?;
+            Ok(template.trim_end().to_string())
+        })
+        .transpose()
+}
+
+fn render_section(
+    template: &Option<String>,
+    default: String,
+    context: &DocumentContext,
+) -> Result<String> {
+    let Some(template) = template else {
+        return Ok(default);
+    };
+    template::render_with(template, |name| match name {
+        "default" => Ok(default.clone()),
+        "classes" => Ok(context.classes.join(", ")),
+        "sources" => Ok(context.sources.join(", ")),
+        "date" => Ok(chrono::Local::now().format("%Y-%m-%d").to_string()),
+        "filename" => Ok(context.filename.clone()),
+        name => Err(Error::TemplateError(format!("Unknown variable: {name}"))),
+    })
+}
+
+impl Prompt {
+    /// Creates the prompt for `profile`, loading any prompt templates.
+    /// Relative template paths are resolved against the profile directory.
+    pub fn new(profile: &Profile) -> Result<Self> {
+        let dir = Profile::get_profile_dir()?;
+        Ok(Prompt {
+            fields: profile.fields.clone(),
+            taxonomy: profile.taxonomy.clone(),
+            hints: profile.hints.clone(),
+            language: profile.language.clone(),
+            review: profile.review.clone(),
+            task: load_template(&dir, &profile.prompt.task)?,
+            outputs: load_template(&dir, &profile.prompt.outputs)?,
+            specs: load_template(&dir, &profile.prompt.specs)?,
+        })
+    }
+
+    pub fn document_data_function(&self) -> serde_json::Value {
+        let mut function = document_data_function();
+        if !self.fields.is_empty() {
+            function["parameters"]["properties"]["extra"] = self.make_extra_schema();
+        }
+        if self.review.is_some() {
+            function["parameters"]["required"]
+                .as_array_mut()
+                .unwrap()
+                .push(json!("confidence"));
+        }
+        if let Some(ref language) = self.language.translation {
+            function["parameters"]["properties"]["summary_translation"] = json!({
+                "type": "string",
+                "description": format!("Your summary of the content translated into {language}"),
+            });
+            function["parameters"]["required"]
+                .as_array_mut()
+                .unwrap()
+                .push(json!("summary_translation"));
+        }
+        if let Some(classes) = self.taxonomy.allowed_classes() {
+            function["parameters"]["properties"]["class"]["enum"] = json!(classes);
+        }
+        if let Some(sources) = self.taxonomy.allowed_sources() {
+            function["parameters"]["properties"]["source"]["enum"] = json!(sources);
+        }
+
+        function
+    }
+
+    pub fn make_system_prompt(&self, context: &DocumentContext) -> Result<String> {
+        let mut outputs = make_outputs();
+        if !self.fields.is_empty() {
+            outputs.push("* The additional fields requested in `extra`, as far as they can be found in the document.".to_string());
+        }
+        if let Some(ref language) = self.language.translation {
+            outputs.push(format!("* A translation of the summary into {language}."));
+        }
+        let mut specs = self.make_language_specs();
+        match self.make_taxonomy_spec("class", &self.taxonomy.classes) {
+            Some(spec) => specs.push(spec),
+            None => specs.extend(make_class_spec(&context.classes)),
+        }
+        match self.make_taxonomy_spec("source", &self.taxonomy.sources) {
+            Some(spec) => specs.push(spec),
+            None => specs.extend(make_source_spec(&context.sources)),
+        }
+
+        let task = render_section(&self.task, TASK.to_string(), context)?;
+        let outputs = render_section(&self.outputs, outputs.join("\n"), context)?;
+        let specs = render_section(&self.specs, specs.join("\n"), context)?;
+        Ok(format!("{task}\n{outputs}\n\nWhen producing the output, you shall observe the following points:\n{specs}\n"))
+    }
+
+    fn make_language_specs(&self) -> Vec<String> {
+        let languages = [
+            ("summary", &self.language.summary),
+            ("title", &self.language.title),
+            ("keywords", &self.language.keywords),
+        ];
+        if languages.iter().all(|(_, language)| language.is_none()) {
+            return vec!["* Please make sure that the language of all outputs matches the language of the input document.".to_string()];
+        }
+
+        std::iter::once("* Unless stated otherwise below, please make sure that the language of all outputs matches the language of the input document.".to_string())
+            .chain(languages.into_iter().filter_map(|(name, language)| {
+                language
+                    .as_ref()
+                    .map(|language| format!("* The {name} must be in {language}, regardless of the language of the document."))
+            }))
+            .collect()
+    }
+
+    /// Describes the document file to the model, as far as enabled by the profile.
+    pub fn make_hints(&self, context: &DocumentContext) -> Option<String> {
+        let mut hints = Vec::new();
+        if self.hints.filename && !context.filename.is_empty() {
+            hints.push(format!("* Original filename: {}", context.filename));
+        }
+        if self.hints.mtime {
+            if let Some(mtime) = context.mtime {
+                hints.push(format!(
+                    "* File last modified: {}",
+                    mtime.format("%Y-%m-%d %H:%M")
+                ));
+            }
+        }
+        if let Some(ref sender) = context.sender {
+            hints.push(format!("* Sent by e-mail from: {sender}"));
+        }
+
+        (!hints.is_empty()).then(|| {
+            format!(
+                "The following information about the file containing the document may help to determine its date and source:\n{}",
+                hints.join("\n")
+            )
+        })
+    }
+
+    fn make_taxonomy_spec(&self, name: &str, entries: &[TaxonomyEntry]) -> Option<String> {
+        if entries.is_empty() {
+            return None;
+        }
+        let entries = entries
+            .iter()
+            .map(|entry| match entry.description {
+                Some(ref description) => format!("  * {}: {}", entry.name, description),
+                None => format!("  * {}", entry.name),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let other = if self.taxonomy.other {
+            format!(" If none of them matches, use `{OTHER}`.")
+        } else {
+            String::new()
+        };
+        Some(format!("* The {name} of the document must be exactly one of the following, do not create new ones.{other}\n{entries}"))
+    }
+
+    fn make_extra_schema(&self) -> serde_json::Value {
+        let properties: serde_json::Map<_, _> = self
+            .fields
+            .iter()
+            .map(|field| {
+                let mut schema = match field.field_type {
+                    FieldType::String => json!({ "type": "string" }),
+                    FieldType::Number => json!({ "type": "number" }),
+                    FieldType::Integer => json!({ "type": "integer" }),
+                    FieldType::Boolean => json!({ "type": "boolean" }),
+                    FieldType::Date => json!({ "type": "string", "format": "date" }),
+                };
+                let description = match (&field.description, field.field_type) {
+                    (Some(description), FieldType::Date) => format!("{description} in YYYY-MM-DD"),
+                    (Some(description), _) => description.clone(),
+                    (None, FieldType::Date) => format!("The {} in YYYY-MM-DD", field.name),
+                    (None, _) => format!("The {}", field.name),
+                };
+                schema["description"] = json!(description);
+                (field.name.clone(), schema)
+            })
+            .collect();
+        let required: Vec<_> = self
+            .fields
+            .iter()
+            .filter(|field| field.required)
+            .map(|field| field.name.clone())
+            .collect();
+
+        json!({
+            "type": "object",
+            "description": "Additional fields extracted from the document",
+            "properties": properties,
+            "required": required,
+        })
+    }
+}
+
+fn document_data_function() -> serde_json::Value {
+    json!({
+        "name": DOCUMENT_DATA_FUNCTION,
+        "description": "Please use this function to return the transcribed content \
+            of the document, your summary of the content, \
+            your classification of the document, the source of the document, \
+            the keywords you assigned, \
+            the title you assigned and the date you determined.",
+        "parameters": {
+            "type": "object",
+            "properties": {
+                "content": {
+                    "type": "string",
+                    "description": "The contents of the document"
+                },
+                "summary": {
+                    "type": "string",
+                    "description": "Your summary of the content"
+                },
+                "class": {
+                    "type": "string",
+                    "description": "The class you assigned to the document"
+                },
+                "source": {
+                    "type": "string",
+                    "description": "The source you assigned to the document"
+                },
+                "keywords": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "The keywords assigned to the document"
+                },
+                "title": {
+                    "type": "string",
+                    "description": "The title assigned to the document"
+                },
+                "date": {
+                    "type": "string",
+                    "description": "The date assigned to the document in YYYY-MM-DD"
+                },
+                "confidence": {
+                    "type": "object",
+                    "description": "Your confidence in each of the values you assigned, from 0 (guessed) to 100 (certain)",
+                    "properties": {
+                        "class": { "type": "integer", "minimum": 0, "maximum": 100 },
+                        "source": { "type": "integer", "minimum": 0, "maximum": 100 },
+                        "keywords": { "type": "integer", "minimum": 0, "maximum": 100 },
+                        "title": { "type": "integer", "minimum": 0, "maximum": 100 },
+                        "date": { "type": "integer", "minimum": 0, "maximum": 100 }
+                    }
+                }
+            },
+            "required": [
+                "summary",
+                "class",
+                "keywords",
+                "title",
+                "date"
+            ]
+        }
+    })
+}
+
+pub fn transcription_function() -> serde_json::Value {
+    json!({
+        "name": TRANSCRIPTION_FUNCTION,
+        "description": "Please use this function to return the complete transcription of the pages.",
+        "parameters": {
+            "type": "object",
+            "properties": {
+                "content": {
+                    "type": "string",
+                    "description": "The complete text of the pages"
+                }
+            },
+            "required": ["content"]
+        }
+    })
+}
+
+fn make_outputs() -> Vec<String> {
+    vec![
+        "* A transcription of the contents of the document. If the document is too large to provide a full transcription, you may omit this.".to_string(),
+         "* A summary of the content of the entire document.".to_string(),
+         "* A classification of the document. Please use rather broad and general concepts as classes. The class must be usable as part of a filename and must not contain whitespaces or non-ascii characters. Please favor using hyphens over underscores as separators. The grammatical number of the word used as class should be singular if possible.".to_string(),
+        "* The source of the document. This could be the author, creator, sender or issuer of the document. The source must be usable as part of a filename and must not contain whitespaces or non-ascii characters.".to_string(),
+        "* Between 2 and 4 keywords describing the content of the document.".to_string(),
+        "* A title describing the document. It should be sufficiently specific to differentiate this particular document from other documents of this class and source, but it should not duplicate words that are already found as class or source. The title must be usable as part of a filename and must not contain whitespaces or non-ascii characters.".to_string(),
+        "* A date to be associated with the document. Please favor the date when the document was issued over any other dates found.".to_string(),
+        "* Your confidence in each of class, source, keywords, title and date, from 0 if you had to guess to 100 if you are certain. Please be honest, low values are expected when the document is ambiguous.".to_string(),
+    ]
+}
+
+fn make_class_spec(classes: &[String]) -> Option<String> {
+    (!classes.is_empty()).then(|| format!("* When choosing the class of the document, check if any of these classes match before creating a new one: {}", classes.join(", ")))
+}
+
+fn make_source_spec(sources: &[String]) -> Option<String> {
+    (!sources.is_empty()).then(|| format!("* When choosing the source of the document, check if any of these sources match before creating a new one: {}", sources.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rstest::rstest;
+    use tempdir::TempDir;
+
+    #[rstest]
+    fn test_extra_schema() {
+        let profile: Profile = serde_yaml::from_str(
+            "name: foo\nfields:\n  - name: invoice_total\n    type: number\n    required: true\n  - name: due_date\n    type: date\n    description: The due date\npaths:\n  path: /home/foo\n  locations: {}\n",
+        )
+        .unwrap();
+        let function = Prompt::new(&profile).unwrap().document_data_function();
+        let extra = &function["parameters"]["properties"]["extra"];
+        assert_eq!(extra["properties"]["invoice_total"]["type"], "number");
+        assert_eq!(
+            extra["properties"]["due_date"]["description"],
+            "The due date in YYYY-MM-DD"
+        );
+        assert_eq!(extra["required"], json!(["invoice_total"]));
+    }
+
+    #[rstest]
+    fn test_transcription_function() {
+        let function = transcription_function();
+        assert_eq!(function["name"], TRANSCRIPTION_FUNCTION);
+        assert_eq!(function["parameters"]["required"], json!(["content"]));
+        assert!(serde_json::from_value::<Transcription>(json!({})).is_err());
+    }
+
+    #[rstest]
+    fn test_no_extra_schema() {
+        let function = Prompt::default().document_data_function();
+        assert!(function["parameters"]["properties"].get("extra").is_none());
+        assert!(function["parameters"]["properties"]["class"]
+            .get("enum")
+            .is_none());
+    }
+
+    #[rstest]
+    fn test_taxonomy() {
+        let profile: Profile = serde_yaml::from_str(
+            "name: foo\ntaxonomy:\n  classes:\n    - name: invoice\n      description: Bills and invoices\n    - name: contract\n  other: true\npaths:\n  path: /home/foo\n  locations: {}\n",
+        )
+        .unwrap();
+        let prompt = Prompt::new(&profile).unwrap();
+        let function = prompt.document_data_function();
+        assert_eq!(
+            function["parameters"]["properties"]["class"]["enum"],
+            json!(["invoice", "contract", "other"])
+        );
+        assert!(function["parameters"]["properties"]["source"]
+            .get("enum")
+            .is_none());
+
+        let system_prompt = prompt
+            .make_system_prompt(&DocumentContext {
+                classes: vec!["invoices".to_string()],
+                sources: vec!["stadtwerke".to_string()],
+                filename: "scan.pdf".to_string(),
+                mtime: None,
+                sender: None,
+            })
+            .unwrap();
+        assert!(system_prompt.contains("  * invoice: Bills and invoices\n  * contract"));
+        assert!(!system_prompt.contains("these classes match"));
+        assert!(system_prompt.contains("these sources match before creating a new one: stadtwerke"));
+    }
+
+    #[rstest]
+    fn test_prompt_templates() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let specs = tmp.path().join("specs.txt");
+        std::fs::write(
+            &specs,
+            "{default}\n* The document was stored as {filename}, known sources are {sources|upper}.\n",
+        )
+        .unwrap();
+        let mut profile = Profile::default();
+        profile.prompt.specs = Some(specs);
+        let system_prompt = Prompt::new(&profile)
+            .unwrap()
+            .make_system_prompt(&DocumentContext {
+                classes: Vec::new(),
+                sources: vec!["stadtwerke".to_string(), "telekom".to_string()],
+                filename: "scan.pdf".to_string(),
+                mtime: None,
+                sender: None,
+            })
+            .unwrap();
+        assert!(system_prompt.starts_with(TASK));
+        assert!(system_prompt.contains("matches the language of the input document."));
+        assert!(system_prompt.ends_with(
+            "* The document was stored as scan.pdf, known sources are STADTWERKE, TELEKOM.\n"
+        ));
+    }
+
+    #[rstest]
+    fn test_prompt_template_unknown_variable() {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let task = tmp.path().join("task.txt");
+        std::fs::write(&task, "{author}").unwrap();
+        let mut profile = Profile::default();
+        profile.prompt.task = Some(task);
+        assert!(matches!(
+            Prompt::new(&profile),
+            Err(Error::TemplateError(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(false, false, None)]
+    #[case(
+        true,
+        false,
+        Some("* Original filename: Scan_2024-03-02_Stadtwerke.pdf")
+    )]
+    #[case(false, true, Some("* File last modified: 2024-03-02 14:31"))]
+    fn test_make_hints(#[case] filename: bool, #[case] mtime: bool, #[case] hint: Option<&str>) {
+        let mut profile = Profile::default();
+        profile.hints.filename = filename;
+        profile.hints.mtime = mtime;
+        let context = DocumentContext {
+            filename: "Scan_2024-03-02_Stadtwerke.pdf".to_string(),
+            mtime: Some(
+                chrono::Local
+                    .with_ymd_and_hms(2024, 3, 2, 14, 31, 0)
+                    .unwrap(),
+            ),
+            ..DocumentContext::default()
+        };
+        let hints = Prompt::new(&profile).unwrap().make_hints(&context);
+        assert_eq!(hints.is_some(), hint.is_some());
+        if let (Some(hints), Some(hint)) = (hints, hint) {
+            assert!(hints.ends_with(hint));
+        }
+    }
+
+    #[rstest]
+    fn test_make_hints_sender() {
+        let context = DocumentContext {
+            sender: Some("billing@stadtwerke.example".to_string()),
+            ..DocumentContext::default()
+        };
+        let hints = Prompt::new(&Profile::default())
+            .unwrap()
+            .make_hints(&context)
+            .unwrap();
+        assert!(hints.ends_with("* Sent by e-mail from: billing@stadtwerke.example"));
+    }
+
+    #[rstest]
+    fn test_language() {
+        let mut profile = Profile::default();
+        profile.language.summary = Some("English".to_string());
+        profile.language.translation = Some("German".to_string());
+        let prompt = Prompt::new(&profile).unwrap();
+        let system_prompt = prompt
+            .make_system_prompt(&DocumentContext::default())
+            .unwrap();
+        assert!(system_prompt.contains("* The summary must be in English"));
+        assert!(!system_prompt.contains("* The title must be in"));
+        assert!(system_prompt.contains("* A translation of the summary into German."));
+
+        let function = prompt.document_data_function();
+        assert!(function["parameters"]["properties"]
+            .get("summary_translation")
+            .is_some());
+        assert!(function["parameters"]["required"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("summary_translation")));
+    }
+}
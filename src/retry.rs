@@ -0,0 +1,117 @@
+use crate::analyzer::{Analyzer, DocumentAnalyzer, DocumentContext};
+use crate::document::DocumentData;
+use crate::error::{Error, Result};
+use crate::file_info::Page;
+use crate::profile::RetryProfile;
+use rand::Rng;
+use std::future::Future;
+use tokio::time::{sleep, Duration};
+
+pub fn backoff_delay(retry: &RetryProfile, attempt: u32) -> Duration {
+    let delay = retry
+        .initial_delay
+        .saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1)))
+        .min(retry.max_delay);
+    let delay = Duration::from_secs(delay);
+    if retry.jitter {
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    } else {
+        delay
+    }
+}
+
+/// A `Retry-After` sent by the server takes precedence over the backoff,
+/// but is capped at `max_delay` so that it cannot hold a slot for hours.
+fn retry_delay(retry: &RetryProfile, err: &Error, attempt: u32) -> Duration {
+    match err.retry_after() {
+        Some(retry_after) => retry_after.min(Duration::from_secs(retry.max_delay)),
+        None => backoff_delay(retry, attempt),
+    }
+}
+
+pub async fn with_retry<T, F, Fut>(retry: &RetryProfile, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Err(err) if err.is_transient() && attempt < retry.max_attempts => {
+                let delay = retry_delay(retry, &err, attempt);
+                log::warn!(
+                    "Attempt {attempt}/{} failed, retrying in {delay:?}: {err}",
+                    retry.max_attempts
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            result => break result,
+        }
+    }
+}
+
+pub struct RetryAnalyzer {
+    retry: RetryProfile,
+    analyzer: Box<Analyzer>,
+}
+
+impl RetryAnalyzer {
+    pub fn new(retry: RetryProfile, analyzer: Analyzer) -> Self {
+        RetryAnalyzer {
+            retry,
+            analyzer: Box::new(analyzer),
+        }
+    }
+}
+
+impl DocumentAnalyzer for RetryAnalyzer {
+    async fn analyze(&self, pages: Vec<Page>, context: DocumentContext) -> Result<DocumentData> {
+        with_retry(&self.retry, || {
+            self.analyzer.analyze_boxed(pages.clone(), context.clone())
+        })
+        .await
+    }
+
+    async fn transcribe(&self, pages: Vec<Page>) -> Result<String> {
+        with_retry(&self.retry, || {
+            self.analyzer.transcribe_boxed(pages.clone())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(1, 2)]
+    #[case(2, 4)]
+    #[case(3, 8)]
+    #[case(10, 300)]
+    fn test_backoff_delay(#[case] attempt: u32, #[case] seconds: u64) {
+        let retry = RetryProfile {
+            jitter: false,
+            ..RetryProfile::default()
+        };
+        assert_eq!(backoff_delay(&retry, attempt), Duration::from_secs(seconds));
+    }
+
+    #[rstest]
+    #[case(Some(30), 30)]
+    #[case(Some(7200), 300)]
+    #[case(None, 4)]
+    fn test_retry_delay(#[case] retry_after: Option<u64>, #[case] seconds: u64) {
+        let retry = RetryProfile {
+            jitter: false,
+            ..RetryProfile::default()
+        };
+        let err = Error::RateLimitError(
+            "Too many requests".to_string(),
+            retry_after.map(Duration::from_secs),
+        );
+        assert_eq!(retry_delay(&retry, &err, 2), Duration::from_secs(seconds));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::document::DocumentData;
+use crate::error::{Error, Result};
+use crate::file_info::FileInfo;
+use crate::paths::Location;
+use crate::profile::{Profile, ReviewProfile};
+use crate::sidecar::{complete, fail, make_original_path, make_path, write_original};
+use crate::util::{file_exists, make_unique_path, run_scheduler};
+use crate::validation::{ValidationOutcome, Validator};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::time::Duration;
+
+const SUFFIX: &str = ".review.json";
+pub const ACCEPTED: &str = "accepted";
+
+impl ReviewProfile {
+    /// Returns the fields whose confidence is below the threshold.
+    pub fn uncertain_fields(&self, document_data: &DocumentData) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter(|field| {
+                document_data
+                    .confidence
+                    .get(*field)
+                    .is_none_or(|confidence| *confidence < self.threshold.into())
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Moves the PDF at `src` into the review location under its proposed name
+/// and stores `document_data` and its `original`, see `file_document`, next to it.
+pub async fn send_to_review(
+    profile: &Profile,
+    src: &Path,
+    document_data: &DocumentData,
+    original: Option<&str>,
+) -> Result<PathBuf> {
+    let dst = make_unique_path(
+        profile.paths.make_root(Location::Review),
+        document_data.make_filename(
+            &profile.layout,
+            &FileInfo::new(src.to_path_buf())?.extension(),
+        )?,
+    )
+    .await?;
+    fs::write(
+        make_path(&dst, SUFFIX),
+        serde_json::to_string_pretty(document_data)?,
+    )
+    .await?;
+    write_original(&dst, original).await?;
+    fs::rename(src, &dst).await?;
+
+    Ok(dst)
+}
+
+/// Loads the review record of the document at `file_path`, as it may have been
+/// corrected, along with the outcome of the original validation stored in it.
+async fn load(
+    file_path: &Path,
+    review_root: &Path,
+) -> Result<(PathBuf, serde_json::Value, ValidationOutcome)> {
+    let filename = file_path.file_name().unwrap_or_default();
+    let candidates = [
+        make_path(file_path, SUFFIX),
+        make_path(&review_root.join(filename), SUFFIX),
+    ];
+    let record_path = candidates
+        .into_iter()
+        .find(file_exists)
+        .ok_or_else(|| Error::MissingReviewDataError(file_path.to_path_buf()))?;
+    let mut record: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&record_path).await?)?;
+    let outcome = match record
+        .as_object_mut()
+        .and_then(|record| record.remove("validation"))
+    {
+        Some(outcome) => serde_json::from_value(outcome)?,
+        None => ValidationOutcome::default(),
+    };
+
+    Ok((record_path, record, outcome))
+}
+
+/// The sidecars stay in the review location when only the document is accepted.
+fn find_original(file_path: &Path, review_root: &Path) -> PathBuf {
+    let filename = file_path.file_name().unwrap_or_default();
+    [file_path, &review_root.join(filename)]
+        .map(make_original_path)
+        .into_iter()
+        .find(file_exists)
+        .unwrap_or_else(|| make_original_path(file_path))
+}
+
+async fn complete_item(profile: &Profile, file_path: &Path, original_path: &Path) -> Result<()> {
+    let review_root = profile.paths.make_root(Location::Review);
+    let (record_path, record, outcome) = load(file_path, &review_root).await?;
+    let document_data = Validator::new(profile)
+        .revalidate(record, outcome)
+        .map_err(Error::ValidationError)?;
+    complete(profile, file_path, &document_data, original_path).await?;
+    fs::remove_file(record_path).await?;
+
+    Ok(())
+}
+
+async fn complete_accepted(profile: &Profile) -> Result<()> {
+    let review_root = profile.paths.make_root(Location::Review);
+    let mut entries = fs::read_dir(review_root.join(ACCEPTED)).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_path = entry.path();
+        if !file_path.is_file() || entry.file_name().to_string_lossy().ends_with(SUFFIX) {
+            continue;
+        }
+
+        log::info!("Completing accepted review item {file_path:?}");
+        let original_path = find_original(&file_path, &review_root);
+        if let Err(err) = complete_item(profile, &file_path, &original_path).await {
+            log::error!("Unable to complete review item: {file_path:?}: {err}");
+            fail(profile, &file_path, &err, &original_path).await;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_review_scheduler(profile: Profile) {
+    run_scheduler(
+        Duration::from_secs(10),
+        "complete accepted review items",
+        || complete_accepted(&profile),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use serde_json::json;
+
+    #[rstest]
+    #[case(json!({ "class": 90, "source": 80, "title": 75, "date": 99 }), vec![])]
+    #[case(json!({ "class": 90, "source": 40, "title": 75, "date": 99 }), vec!["source"])]
+    #[case(json!({ "class": 90, "title": 75, "date": 10 }), vec!["source", "date"])]
+    #[case(json!({ "class": 69.5, "source": 70.0, "title": 75, "date": 99 }), vec!["class"])]
+    fn test_uncertain_fields(#[case] confidence: serde_json::Value, #[case] fields: Vec<&str>) {
+        let document_data: DocumentData = serde_json::from_value(json!({
+            "summary": "Foo",
+            "class": "invoice",
+            "source": "stadtwerke",
+            "keywords": ["foo"],
+            "title": "electricity-march",
+            "date": "2024-03-01",
+            "confidence": confidence,
+        }))
+        .unwrap();
+        assert_eq!(
+            ReviewProfile::default().uncertain_fields(&document_data),
+            fields
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_complete_item_keeps_validation() {
+        let tmp = tempdir::TempDir::new("mrdocument-test").unwrap();
+        let mut profile = Profile::default();
+        profile.paths = profile.paths.with_path(tmp.path().to_path_buf());
+        let review_root = profile.paths.make_root(Location::Review);
+        fs::create_dir_all(review_root.join(ACCEPTED))
+            .await
+            .unwrap();
+        let file_path = review_root.join(ACCEPTED).join("scan.png");
+        fs::write(&file_path, b"\x89PNG\r\n\x1a\n").await.unwrap();
+        let record = json!({
+            "summary": "Foo",
+            "class": "invoice",
+            "source": "stadtwerke",
+            "keywords": ["electricity"],
+            "title": "electricity-march",
+            "date": "2024-03-01",
+            "validation": { "rounds": 1, "repaired": ["`date` is invalid"] },
+        });
+        fs::write(
+            make_path(&review_root.join("scan.png"), SUFFIX),
+            record.to_string(),
+        )
+        .await
+        .unwrap();
+
+        complete_item(&profile, &file_path, &make_original_path(&file_path))
+            .await
+            .unwrap();
+        let metadata = fs::read_to_string(
+            profile
+                .paths
+                .make_root(Location::Outbox)
+                .join("invoice/stadtwerke/2024-03-01-electricity-march.metadata.json"),
+        )
+        .await
+        .unwrap();
+        assert!(metadata.contains("passed after 1 repair round(s), fixed: `date` is invalid"));
+    }
+}
@@ -0,0 +1,107 @@
+use deunicode::deunicode_char;
+use std::path::PathBuf;
+
+/// Maximum length of a single path component in bytes, as supported by common filesystems.
+pub const MAX_COMPONENT_BYTES: usize = 255;
+
+const RESERVED_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+fn transliterate_char(c: char) -> String {
+    match c {
+        'ä' => "ae".to_string(),
+        'ö' => "oe".to_string(),
+        'ü' => "ue".to_string(),
+        'Ä' => "Ae".to_string(),
+        'Ö' => "Oe".to_string(),
+        'Ü' => "Ue".to_string(),
+        'ß' => "ss".to_string(),
+        c if c.is_ascii() => c.to_string(),
+        c => deunicode_char(c).unwrap_or("-").to_string(),
+    }
+}
+
+/// Turns `value` into a single path component that is safe on common filesystems:
+/// Non-ASCII characters are transliterated, path separators, reserved and whitespace
+/// characters are replaced by hyphens, leading dots are removed and the result is
+/// limited to `max_bytes`.
+pub fn sanitize_component(value: &str, max_bytes: usize) -> String {
+    let transliterated: String = value.chars().map(transliterate_char).collect();
+    let mut result = String::new();
+    for c in transliterated.chars() {
+        let c = if c.is_whitespace() || c.is_control() || RESERVED_CHARS.contains(&c) {
+            '-'
+        } else {
+            c
+        };
+        if !(c == '-' && result.ends_with('-')) {
+            result.push(c);
+        }
+    }
+
+    let mut result = result
+        .trim_matches(|c: char| c == '-' || c == '.')
+        .to_string();
+    if RESERVED_NAMES.contains(&result.to_lowercase().as_str()) {
+        result.insert(0, '_');
+    }
+    result.truncate(max_bytes);
+    let result = result.trim_end_matches(['-', '.']).to_string();
+
+    if result.is_empty() {
+        "unnamed".to_string()
+    } else {
+        result
+    }
+}
+
+/// Sanitizes each component of the relative path `value`. Empty, `.` and `..`
+/// components are dropped, so the result always stays below the directory it is joined to.
+pub fn sanitize_path(value: &str) -> PathBuf {
+    value
+        .split(['/', '\\'])
+        .filter(|component| !component.trim().trim_matches('.').is_empty())
+        .map(|component| sanitize_component(component, MAX_COMPONENT_BYTES))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("Stromrechnung März", "Stromrechnung-Maerz")]
+    #[case("Größe & Gewicht", "Groesse-&-Gewicht")]
+    #[case("Café Crème", "Cafe-Creme")]
+    #[case("../../etc/passwd", "etc-passwd")]
+    #[case("a/b\\c:d*e?f\"g<h>i|j", "a-b-c-d-e-f-g-h-i-j")]
+    #[case("  ..hidden  ", "hidden")]
+    #[case("..", "unnamed")]
+    #[case("", "unnamed")]
+    #[case("CON", "_CON")]
+    #[case("v1.2.3", "v1.2.3")]
+    #[case("line\nbreak\ttab", "line-break-tab")]
+    fn test_sanitize_component(#[case] value: &str, #[case] expected: &str) {
+        assert_eq!(sanitize_component(value, MAX_COMPONENT_BYTES), expected);
+    }
+
+    #[rstest]
+    fn test_sanitize_component_length() {
+        let result = sanitize_component(&"ä".repeat(200), 255);
+        assert_eq!(result.len(), 255);
+        assert!(result.is_ascii());
+    }
+
+    #[rstest]
+    #[case("invoice/stadtwerke", "invoice/stadtwerke")]
+    #[case("/invoice//stadtwerke/", "invoice/stadtwerke")]
+    #[case("../invoice/./x", "invoice/x")]
+    #[case("2024/Überweisung", "2024/Ueberweisung")]
+    fn test_sanitize_path(#[case] value: &str, #[case] expected: &str) {
+        assert_eq!(sanitize_path(value), PathBuf::from(expected));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::document::DocumentData;
+use crate::error::{Error, Result};
+use crate::error_record::ErrorRecord;
+use crate::outbox::file_document;
+use crate::paths::Location;
+use crate::profile::Profile;
+use crate::util::move_into;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Remembers the original of a held back document, see `file_document`.
+const ORIGINAL_SUFFIX: &str = ".original";
+
+/// Returns the path of the sidecar file with `suffix` for the file at `file_path`.
+pub fn make_path(file_path: &Path, suffix: &str) -> PathBuf {
+    let mut filename = file_path.file_name().unwrap_or_default().to_os_string();
+    filename.push(suffix);
+    file_path.with_file_name(filename)
+}
+
+/// Returns the path of the file the sidecar at `sidecar_path` belongs to,
+/// if it is a sidecar with `suffix`.
+pub fn strip_suffix(sidecar_path: &Path, suffix: &str) -> Option<PathBuf> {
+    let filename = sidecar_path.file_name()?.to_str()?;
+    filename
+        .strip_suffix(suffix)
+        .filter(|filename| !filename.is_empty())
+        .map(|filename| sidecar_path.with_file_name(filename))
+}
+
+/// Returns the path of the sidecar remembering the original of the held back
+/// document at `file_path`.
+pub fn make_original_path(file_path: &Path) -> PathBuf {
+    make_path(file_path, ORIGINAL_SUFFIX)
+}
+
+/// Remembers `original` for the held back document at `file_path`.
+pub async fn write_original(file_path: &Path, original: Option<&str>) -> Result<()> {
+    if let Some(original) = original {
+        fs::write(make_original_path(file_path), original).await?;
+    }
+
+    Ok(())
+}
+
+async fn read_original(original_path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(original_path).await {
+        Ok(original) => Ok(Some(original)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::from(err)),
+    }
+}
+
+/// Files the held back document at `file_path` into the outbox according to
+/// `document_data`, along with the original remembered at `original_path`,
+/// and moves it to the processed location.
+pub async fn complete(
+    profile: &Profile,
+    file_path: &Path,
+    document_data: &DocumentData,
+    original_path: &Path,
+) -> Result<()> {
+    if !fs::try_exists(file_path).await? {
+        return Err(Error::FileDisappearedError(file_path.to_path_buf()));
+    }
+    let original = read_original(original_path).await?;
+    file_document(
+        profile,
+        file_path.to_path_buf(),
+        document_data,
+        original.as_deref(),
+    )
+    .await?;
+    move_into(file_path, profile.paths.make_root(Location::Processed)).await?;
+    if original.is_some() {
+        fs::remove_file(original_path).await?;
+    }
+
+    Ok(())
+}
+
+/// Moves the held back document at `file_path`, if it is still there,
+/// to the error location and records `err` along with the original
+/// remembered at `original_path`, so that it is kept when requeued.
+pub async fn fail(profile: &Profile, file_path: &Path, err: &Error, original_path: &Path) {
+    if !fs::try_exists(file_path).await.unwrap_or_default() {
+        return;
+    }
+    let original = read_original(original_path)
+        .await
+        .inspect_err(|e| log::warn!("Unable to read {original_path:?}: {e}"))
+        .ok()
+        .flatten();
+    match move_into(file_path, profile.paths.make_root(Location::Error)).await {
+        Ok(error_path) => {
+            if original.is_some() {
+                fs::remove_file(original_path)
+                    .await
+                    .inspect_err(|e| log::warn!("Unable to remove {original_path:?}: {e}"))
+                    .ok();
+            }
+            ErrorRecord::record(&error_path, err, original.as_deref())
+                .await
+                .inspect_err(|e| log::warn!("Unable to write error record: {e:?}"))
+                .ok();
+        }
+        Err(e) => log::error!("Unable to move file to error location: {file_path:?}: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("/pending/foo.pdf.proposal.yaml", Some("/pending/foo.pdf"))]
+    #[case("/pending/foo.pdf", None)]
+    #[case("/pending/.proposal.yaml", None)]
+    fn test_strip_suffix(#[case] sidecar_path: &str, #[case] file_path: Option<&str>) {
+        assert_eq!(
+            strip_suffix(Path::new(sidecar_path), ".proposal.yaml"),
+            file_path.map(PathBuf::from)
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_fail_keeps_original() {
+        let tmp = tempdir::TempDir::new("mrdocument-test").unwrap();
+        let mut profile = Profile::default();
+        profile.paths = profile.paths.with_path(tmp.path().to_path_buf());
+        let pending = profile.paths.make_root(Location::Pending);
+        fs::create_dir_all(&pending).await.unwrap();
+        fs::create_dir_all(profile.paths.make_root(Location::Error))
+            .await
+            .unwrap();
+        let file_path = pending.join("letter.pdf");
+        fs::write(&file_path, b"%PDF-1.7\n").await.unwrap();
+        write_original(&file_path, Some("letter.docx"))
+            .await
+            .unwrap();
+
+        let original_path = make_original_path(&file_path);
+        fail(&profile, &file_path, &Error::SkelError, &original_path).await;
+        assert!(!original_path.exists());
+        let error_path = profile.paths.make_root(Location::Error).join("letter.pdf");
+        let record = ErrorRecord::load(&error_path).await.unwrap();
+        assert_eq!(record.original.as_deref(), Some("letter.docx"));
+    }
+
+    #[rstest]
+    fn test_make_path() {
+        let file_path = Path::new("/review/2024-03-01-electricity-march.pdf");
+        let sidecar_path = make_path(file_path, ".review.json");
+        assert_eq!(
+            sidecar_path,
+            Path::new("/review/2024-03-01-electricity-march.pdf.review.json")
+        );
+        assert_eq!(
+            strip_suffix(&sidecar_path, ".review.json").as_deref(),
+            Some(file_path)
+        );
+    }
+}
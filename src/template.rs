@@ -0,0 +1,279 @@
+use crate::document::DocumentData;
+use crate::error::{Error, Result};
+use chrono::NaiveDate;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Filter {
+    Lower,
+    Upper,
+    Max(usize),
+    Default(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Variable { name: String, filters: Vec<Filter> },
+}
+
+fn parse_filter(filter: &str) -> Result<Filter> {
+    let (name, arg) = match filter.split_once(':') {
+        Some((name, arg)) => (name.trim(), Some(arg)),
+        None => (filter.trim(), None),
+    };
+    match (name, arg) {
+        ("lower", None) => Ok(Filter::Lower),
+        ("upper", None) => Ok(Filter::Upper),
+        ("max", Some(arg)) => arg
+            .trim()
+            .parse()
+            .map(Filter::Max)
+            .map_err(|_| Error::TemplateError(format!("Invalid length in filter: {filter}"))),
+        ("default", Some(arg)) => Ok(Filter::Default(arg.to_string())),
+        _ => Err(Error::TemplateError(format!("Unknown filter: {filter}"))),
+    }
+}
+
+fn parse_variable(variable: &str) -> Result<Segment> {
+    let mut parts = variable.split('|');
+    let name = parts.next().unwrap_or_default().trim().to_string();
+    if name.is_empty() {
+        return Err(Error::TemplateError("Empty variable".to_string()));
+    }
+    let filters = parts.map(parse_filter).collect::<Result<_>>()?;
+
+    Ok(Segment::Variable { name, filters })
+}
+
+fn parse(template: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut variable = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => variable.push(c),
+                        None => {
+                            return Err(Error::TemplateError(format!(
+                                "Unclosed variable in {template:?}"
+                            )))
+                        }
+                    }
+                }
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(parse_variable(&variable)?);
+            }
+            '}' => {
+                return Err(Error::TemplateError(format!(
+                    "Unmatched '}}' in {template:?}"
+                )));
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+
+    Ok(segments)
+}
+
+/// The variables available for every document, in addition to extra fields.
+pub const VARIABLES: &[&str] = &["date", "year", "month", "day", "class", "source", "title"];
+
+fn lookup(name: &str, document_data: &DocumentData) -> Result<String> {
+    let date = || {
+        NaiveDate::parse_from_str(&document_data.date, "%Y-%m-%d")
+            .map_err(|_| Error::TemplateError(format!("Invalid date: {:?}", document_data.date)))
+    };
+    Ok(match name {
+        "date" => document_data.date.clone(),
+        "year" => date()?.format("%Y").to_string(),
+        "month" => date()?.format("%m").to_string(),
+        "day" => date()?.format("%d").to_string(),
+        "class" => document_data.class.clone(),
+        "source" => document_data.source.clone(),
+        "title" => document_data.title.clone(),
+        name => match document_data.extra.get(name) {
+            None | Some(serde_json::Value::Null) => String::new(),
+            Some(serde_json::Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+        },
+    })
+}
+
+fn apply(filter: &Filter, value: String) -> String {
+    match filter {
+        Filter::Lower => value.to_lowercase(),
+        Filter::Upper => value.to_uppercase(),
+        Filter::Max(len) => value.chars().take(*len).collect(),
+        Filter::Default(default) if value.is_empty() => default.clone(),
+        Filter::Default(_) => value,
+    }
+}
+
+/// Checks that `template` is well-formed.
+pub fn check(template: &str) -> Result<()> {
+    parse(template).map(|_| ())
+}
+
+/// Checks that `template` is well-formed and only uses variables for which
+/// `is_known` holds.
+pub fn check_with<F>(template: &str, is_known: F) -> Result<()>
+where
+    F: Fn(&str) -> bool,
+{
+    for segment in parse(template)? {
+        if let Segment::Variable { name, .. } = segment {
+            if !is_known(&name) {
+                return Err(Error::TemplateError(format!(
+                    "Unknown variable {name:?} in {template:?}"
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks whether `template` refers to the variable `name`.
+pub fn uses(template: &str, name: &str) -> Result<bool> {
+    Ok(parse(template)?.iter().any(
+        |segment| matches!(segment, Segment::Variable { name: variable, .. } if variable == name),
+    ))
+}
+
+/// The depth, counting from 1, of the first directory of the path `template`
+/// that consists of nothing but the variable `name`, e.g. 2 for `class` in
+/// `{year}/{class|lower}`.
+pub fn variable_depth(template: &str, name: &str) -> Result<Option<usize>> {
+    for (i, component) in template.split('/').enumerate() {
+        if let [Segment::Variable { name: variable, .. }] = &parse(component)?[..] {
+            if variable == name {
+                return Ok(Some(i + 1));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Renders `template`, resolving variables through `lookup`.
+pub fn render_with<F>(template: &str, lookup: F) -> Result<String>
+where
+    F: Fn(&str) -> Result<String>,
+{
+    parse(template)?
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Literal(literal) => Ok(literal),
+            Segment::Variable { name, filters } => Ok(filters
+                .iter()
+                .fold(lookup(&name)?, |value, filter| apply(filter, value))),
+        })
+        .collect()
+}
+
+/// Renders `template` for `document_data`. Path separators in substituted
+/// values are replaced, so only the template itself can introduce directories.
+pub fn render(template: &str, document_data: &DocumentData) -> Result<String> {
+    render_with(template, |name| {
+        Ok(lookup(name, document_data)?.replace(['/', '\\'], "-"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::ValidationOutcome;
+    use rstest::rstest;
+    use std::collections::BTreeMap;
+
+    fn make_document_data() -> DocumentData {
+        DocumentData {
+            content: None,
+            summary: "Foo".to_string(),
+            summary_translation: None,
+            class: "Invoice".to_string(),
+            source: "Stadtwerke".to_string(),
+            keywords: Vec::new(),
+            title: "electricity-march".to_string(),
+            date: "2024-03-01".to_string(),
+            extra: BTreeMap::from([
+                ("invoice_total".to_string(), serde_json::json!(42.5)),
+                ("customer".to_string(), serde_json::json!("ACME/Berlin")),
+            ]),
+            email: None,
+            validation: ValidationOutcome::default(),
+            confidence: BTreeMap::new(),
+        }
+    }
+
+    #[rstest]
+    #[case("{class|lower}/{source|lower}", "invoice/stadtwerke")]
+    #[case("{date}-{title}", "2024-03-01-electricity-march")]
+    #[case("{year}/{month}/{day}-{source|upper}", "2024/03/01-STADTWERKE")]
+    #[case("{title|max:5}", "elect")]
+    #[case("{date}-{invoice_total}-{customer}", "2024-03-01-42.5-ACME-Berlin")]
+    #[case("{iban|default:none}", "none")]
+    #[case("{{{class}}}", "{Invoice}")]
+    fn test_render(#[case] template: &str, #[case] expected: &str) {
+        assert_eq!(render(template, &make_document_data()).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("{class")]
+    #[case("class}")]
+    #[case("{}")]
+    #[case("{class|reverse}")]
+    #[case("{class|max:ten}")]
+    fn test_check(#[case] template: &str) {
+        assert!(check(template).is_err());
+    }
+
+    #[rstest]
+    #[case("{input}", "input", true)]
+    #[case("--out={outdir|lower}", "outdir", true)]
+    #[case("{{input}}", "input", false)]
+    #[case("{outdir}", "input", false)]
+    fn test_uses(#[case] template: &str, #[case] name: &str, #[case] expected: bool) {
+        assert_eq!(uses(template, name).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("{class|lower}/{source|lower}", "class", Some(1))]
+    #[case("{class|lower}/{source|lower}", "source", Some(2))]
+    #[case("{year}/{class|lower}", "class", Some(2))]
+    #[case("{year}/{class|lower}", "source", None)]
+    #[case("{year}/{class}-{source}", "class", None)]
+    fn test_variable_depth(
+        #[case] template: &str,
+        #[case] name: &str,
+        #[case] depth: Option<usize>,
+    ) {
+        assert_eq!(variable_depth(template, name).unwrap(), depth);
+    }
+
+    #[rstest]
+    #[case("{date}-{title|max:10}", true)]
+    #[case("{{\"title\": \"{title}\"}}", true)]
+    #[case("{date}-{tilte}", false)]
+    fn test_check_with(#[case] template: &str, #[case] valid: bool) {
+        let known = |name: &str| ["date", "title"].contains(&name);
+        assert_eq!(check_with(template, known).is_ok(), valid);
+    }
+}
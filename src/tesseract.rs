@@ -0,0 +1,185 @@
+use crate::analyzer::{Analyzer, DocumentAnalyzer, DocumentContext};
+use crate::document::DocumentData;
+use crate::error::{Error, Result};
+use crate::file_info::Page;
+use crate::profile::TesseractBackend;
+use crate::validation::ValidationOutcome;
+use base64::{engine::general_purpose, Engine as _};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use tempdir::TempDir;
+use tokio::fs;
+use tokio::process::Command;
+
+async fn recognize(language: &Option<String>, page: Page) -> Result<String> {
+    let data = match page {
+        Page::Text(text) => return Ok(text),
+        Page::Image { data, .. } => general_purpose::STANDARD
+            .decode(data)
+            .map_err(|_| Error::EncodingError)?,
+    };
+
+    let tmp_dir = TempDir::new("mrdocument")?;
+    let image_path = tmp_dir.path().join("page");
+    fs::write(&image_path, data).await?;
+
+    let mut command = Command::new("tesseract");
+    command.arg(&image_path).arg("stdout");
+    if let Some(language) = language {
+        command.arg("-l").arg(language);
+    }
+    let output = command.output().await.map_err(|err| {
+        if err.kind() == ErrorKind::NotFound {
+            Error::DependencyMissingError("tesseract".to_string())
+        } else {
+            Error::from(err)
+        }
+    })?;
+    if !output.status.success() {
+        return Err(Error::OcrError(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_matches(|c: char| c.is_whitespace() || c == '\x0c')
+        .to_string())
+}
+
+fn make_title(content: &str) -> String {
+    let title = content
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .take(6)
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase();
+
+    if title.is_empty() {
+        "document".to_string()
+    } else {
+        title
+    }
+}
+
+fn make_summary(content: &str) -> String {
+    content
+        .split_whitespace()
+        .scan(0, |len, word| {
+            *len += word.len() + 1;
+            (*len <= 300).then_some(word)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub struct TesseractAnalyzer {
+    language: Option<String>,
+    classifier: Option<Box<Analyzer>>,
+}
+
+impl TesseractAnalyzer {
+    pub fn new(backend: TesseractBackend, classifier: Option<Analyzer>) -> Self {
+        TesseractAnalyzer {
+            language: backend.language,
+            classifier: classifier.map(Box::new),
+        }
+    }
+
+    async fn recognize_pages(&self, pages: Vec<Page>) -> Result<Vec<String>> {
+        let mut texts = Vec::new();
+        for (i, page) in pages.into_iter().enumerate() {
+            log::debug!("Running OCR on page {}", i + 1);
+            texts.push(recognize(&self.language, page).await?);
+        }
+
+        Ok(texts)
+    }
+}
+
+impl DocumentAnalyzer for TesseractAnalyzer {
+    async fn analyze(&self, pages: Vec<Page>, context: DocumentContext) -> Result<DocumentData> {
+        let texts = self.recognize_pages(pages).await?;
+        let content = texts.join("\n\n");
+
+        match self.classifier {
+            Some(ref classifier) => {
+                log::info!("Classifying OCR text");
+                let pages = texts.into_iter().map(Page::Text).collect();
+                let mut document_data = classifier.analyze_boxed(pages, context).await?;
+                document_data.content = Some(content);
+                Ok(document_data)
+            }
+            None => Ok(DocumentData {
+                summary: make_summary(&content),
+                summary_translation: None,
+                class: "unclassified".to_string(),
+                source: "unknown".to_string(),
+                keywords: Vec::new(),
+                title: make_title(&content),
+                date: context
+                    .mtime
+                    .unwrap_or_else(chrono::Local::now)
+                    .format("%Y-%m-%d")
+                    .to_string(),
+                content: Some(content),
+                extra: BTreeMap::new(),
+                email: None,
+                validation: ValidationOutcome::default(),
+                confidence: BTreeMap::new(),
+            }),
+        }
+    }
+
+    async fn transcribe(&self, pages: Vec<Page>) -> Result<String> {
+        Ok(self.recognize_pages(pages).await?.join("\n\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        "\n  Stadtwerke Musterstadt GmbH\nRechnung",
+        "stadtwerke-musterstadt-gmbh"
+    )]
+    #[case(
+        "Invoice No. 2024/117 for services rendered in March",
+        "invoice-no-2024-117-for-services"
+    )]
+    #[case("  \n\n", "document")]
+    fn test_make_title(#[case] content: &str, #[case] title: &str) {
+        assert_eq!(make_title(content), title);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_analyze_uses_mtime() {
+        let analyzer = TesseractAnalyzer::new(
+            TesseractBackend {
+                language: None,
+                classifier: None,
+            },
+            None,
+        );
+        let context = DocumentContext {
+            mtime: chrono::Local
+                .with_ymd_and_hms(2019, 5, 17, 12, 0, 0)
+                .single(),
+            ..DocumentContext::default()
+        };
+        let document_data = analyzer
+            .analyze(vec![Page::Text("Rechnung".to_string())], context)
+            .await
+            .unwrap();
+        assert_eq!(document_data.date, "2019-05-17");
+        assert_eq!(document_data.title, "rechnung");
+    }
+}
@@ -1,29 +1,72 @@
 use crate::error::{Error, Result};
-use std::path::PathBuf;
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
 use tokio::fs;
 use tokio::fs::create_dir_all;
+use tokio::time::{sleep, Duration};
 use uuid::Uuid;
 
 pub fn file_exists(path: &PathBuf) -> bool {
-    if let Ok(exists) = std::fs::exists(&path) {
-        exists
-    } else {
-        false
+    std::fs::exists(path).unwrap_or_default()
+}
+
+/// The names of the directories in `path`, sorted.
+pub async fn list_dirs(path: &Path) -> Result<Vec<String>> {
+    let mut dirs = Vec::new();
+    let mut entries = fs::read_dir(path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.metadata().await?.is_dir() {
+            dirs.push(entry.file_name().to_string_lossy().into_owned());
+        }
     }
+    dirs.sort();
+
+    Ok(dirs)
+}
+
+/// The distinct names of the directories `depth` levels below `root`, sorted.
+pub async fn list_dirs_at(root: &Path, depth: usize) -> Result<Vec<String>> {
+    let mut dirs = vec![root.to_path_buf()];
+    for _ in 0..depth {
+        let mut next = Vec::new();
+        for dir in dirs {
+            for name in list_dirs(&dir).await? {
+                next.push(dir.join(name));
+            }
+        }
+        dirs = next;
+    }
+    let mut names: Vec<_> = dirs
+        .iter()
+        .filter_map(|dir| Some(dir.file_name()?.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    Ok(names)
+}
+
+/// Length of the suffix `make_unique_path` may append to a filename stem.
+pub const UNIQUE_SUFFIX_BYTES: usize = 37;
+
+pub fn is_relative_below(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_)))
 }
 
 pub async fn make_unique_path(path: PathBuf, filename: String) -> Result<PathBuf> {
-    let path = path.clone();
+    if Path::new(&filename).components().count() != 1 || !is_relative_below(Path::new(&filename)) {
+        return Err(Error::InvalidPathError(path.join(filename)));
+    }
     create_dir_all(path.clone()).await?;
-    let mut split = filename.split(".");
-    let filename_stem = split.next().expect("Filename needs to have extension");
-    let filename_ext = split.next().expect("Filename needs to have extension");
-    let mut filename = format!("{}.{}", filename_stem, filename_ext);
 
-    if let Err(e) = fs::File::create_new(path.join(filename.clone())).await {
+    if let Err(e) = fs::File::create_new(path.join(&filename)).await {
         log::trace!("unable to create file: {filename}: {e:?}");
-        filename = format!("{}-{}.{}", filename_stem, Uuid::new_v4(), filename_ext);
-        if let Ok(_) = fs::File::create_new(path.join(filename.clone())).await {
+        let filename = match filename.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() => format!("{stem}-{}.{ext}", Uuid::new_v4()),
+            _ => format!("{filename}-{}", Uuid::new_v4()),
+        };
+        if fs::File::create_new(path.join(&filename)).await.is_ok() {
             Ok(path.join(filename))
         } else {
             Err(Error::UnexpectedError(
@@ -35,6 +78,19 @@ pub async fn make_unique_path(path: PathBuf, filename: String) -> Result<PathBuf
     }
 }
 
+/// Moves `src` into the directory `dir`, keeping its filename if it is not taken yet.
+pub async fn move_into(src: &Path, dir: PathBuf) -> Result<PathBuf> {
+    let filename = src
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let dst = make_unique_path(dir, filename).await?;
+    fs::rename(src, &dst).await?;
+
+    Ok(dst)
+}
+
 pub async fn move_file(from: &PathBuf, to: &PathBuf) -> Result<()> {
     fs::File::create_new(to).await?;
     let result = fs::rename(from, to).await;
@@ -47,3 +103,77 @@ pub async fn move_file(from: &PathBuf, to: &PathBuf) -> Result<()> {
         }
     }
 }
+
+/// Runs `task` every `interval` forever, logging failures as being unable to `what`.
+pub async fn run_scheduler<F, Fut>(interval: Duration, what: &str, task: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    loop {
+        task()
+            .await
+            .inspect_err(|e| log::warn!("Unable to {what}: {e:?}"))
+            .ok();
+        sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use tempdir::TempDir;
+
+    #[rstest]
+    #[case("2024-03-01-total-42.5.pdf", "2024-03-01-total-42.5-", ".pdf")]
+    #[case("README", "README-", "")]
+    #[tokio::test]
+    async fn test_make_unique_path(
+        #[case] filename: &str,
+        #[case] prefix: &str,
+        #[case] suffix: &str,
+    ) {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        let first = make_unique_path(tmp.path().to_path_buf(), filename.to_string())
+            .await
+            .unwrap();
+        assert_eq!(first, tmp.path().join(filename));
+        let second = make_unique_path(tmp.path().to_path_buf(), filename.to_string())
+            .await
+            .unwrap();
+        let second = second.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(second.starts_with(prefix) && second.ends_with(suffix));
+        assert_eq!(second.len(), filename.len() + UNIQUE_SUFFIX_BYTES);
+    }
+
+    #[rstest]
+    #[case(1, vec!["2023", "2024"])]
+    #[case(2, vec!["insurance", "invoice"])]
+    #[case(3, vec![])]
+    #[tokio::test]
+    async fn test_list_dirs_at(#[case] depth: usize, #[case] names: Vec<&str>) {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        for dir in ["2023/invoice", "2024/invoice", "2024/insurance"] {
+            create_dir_all(tmp.path().join(dir)).await.unwrap();
+        }
+        fs::write(tmp.path().join("2024/notes.txt"), b"")
+            .await
+            .unwrap();
+        assert_eq!(list_dirs_at(tmp.path(), depth).await.unwrap(), names);
+    }
+
+    #[rstest]
+    #[case("../escape.pdf")]
+    #[case("sub/dir.pdf")]
+    #[case("..")]
+    #[tokio::test]
+    async fn test_make_unique_path_invalid(#[case] filename: &str) {
+        let tmp = TempDir::new("mrdocument-test").unwrap();
+        assert!(
+            make_unique_path(tmp.path().to_path_buf(), filename.to_string())
+                .await
+                .is_err()
+        );
+    }
+}
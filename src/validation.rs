@@ -0,0 +1,379 @@
+use crate::analyzer::DocumentContext;
+use crate::document::DocumentData;
+use crate::profile::{
+    AliasProfile, FieldProfile, FieldType, Profile, ReviewProfile, TaxonomyProfile,
+    ValidationProfile,
+};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ValidationOutcome {
+    pub rounds: u32,
+    pub repaired: Vec<String>,
+    #[serde(default)]
+    pub date_fallback: bool,
+}
+
+impl ValidationOutcome {
+    pub fn summary(&self) -> String {
+        let summary = if self.repaired.is_empty() {
+            "passed".to_string()
+        } else {
+            format!(
+                "passed after {} repair round(s), fixed: {}",
+                self.rounds,
+                self.repaired.join("; ")
+            )
+        };
+        if self.date_fallback {
+            format!("{summary}, date taken from file modification time")
+        } else {
+            summary
+        }
+    }
+
+    /// Adds the outcome of validating the document again, e.g. after it was corrected.
+    pub fn merge(mut self, other: ValidationOutcome) -> Self {
+        self.rounds += other.rounds;
+        self.repaired.extend(other.repaired);
+        self.date_fallback |= other.date_fallback;
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Validator {
+    fields: Vec<FieldProfile>,
+    taxonomy: TaxonomyProfile,
+    aliases: AliasProfile,
+    date_fallback: bool,
+    translation: bool,
+    review: Option<ReviewProfile>,
+    validation: ValidationProfile,
+}
+
+fn is_date(value: &str) -> bool {
+    value.len() == 10 && NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+}
+
+fn check_filename_component(name: &str, value: &str, errors: &mut Vec<String>) {
+    if value.is_empty() {
+        errors.push(format!("`{name}` must not be empty"));
+    } else if value.starts_with('.') {
+        errors.push(format!("`{name}` must not start with a dot: {value:?}"));
+    } else if !value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        errors.push(format!(
+            "`{name}` must only contain ascii letters, digits, hyphens, underscores and dots: {value:?}"
+        ));
+    }
+}
+
+fn check_allowed(name: &str, value: &str, allowed: Option<Vec<String>>, errors: &mut Vec<String>) {
+    if let Some(allowed) = allowed {
+        if !allowed.iter().any(|entry| entry == value) {
+            errors.push(format!(
+                "`{name}` must be one of {}: {value:?}",
+                allowed.join(", ")
+            ));
+        }
+    }
+}
+
+impl Validator {
+    pub fn new(profile: &Profile) -> Self {
+        Validator {
+            fields: profile.fields.clone(),
+            taxonomy: profile.taxonomy.clone(),
+            aliases: profile.aliases.clone(),
+            date_fallback: profile.hints.date_fallback,
+            translation: profile.language.translation.is_some(),
+            review: profile.review.clone(),
+            validation: profile.validation.clone(),
+        }
+    }
+
+    pub fn repair_rounds(&self) -> u32 {
+        self.validation.repair_rounds
+    }
+
+    pub fn validate(
+        &self,
+        value: serde_json::Value,
+        context: &DocumentContext,
+    ) -> Result<DocumentData, Vec<String>> {
+        let mut document_data: DocumentData = serde_json::from_value(value)
+            .map_err(|e| vec![format!("The response does not match the schema: {e}")])?;
+        document_data.class = self.aliases.canonical_class(&document_data.class);
+        document_data.source = self.aliases.canonical_source(&document_data.source);
+
+        let mut errors = Vec::new();
+        if !is_date(&document_data.date) && self.date_fallback {
+            if let Some(mtime) = context.mtime {
+                log::info!(
+                    "No usable date {:?}, using file modification time",
+                    document_data.date
+                );
+                document_data.date = mtime.format("%Y-%m-%d").to_string();
+                document_data.validation.date_fallback = true;
+            }
+        }
+        if !is_date(&document_data.date) {
+            errors.push(format!(
+                "`date` must be a valid date in YYYY-MM-DD: {:?}",
+                document_data.date
+            ));
+        }
+        check_filename_component("class", &document_data.class, &mut errors);
+        check_filename_component("source", &document_data.source, &mut errors);
+        check_filename_component("title", &document_data.title, &mut errors);
+        check_allowed(
+            "class",
+            &document_data.class,
+            self.taxonomy.allowed_classes(),
+            &mut errors,
+        );
+        check_allowed(
+            "source",
+            &document_data.source,
+            self.taxonomy.allowed_sources(),
+            &mut errors,
+        );
+        if document_data.keywords.is_empty()
+            || document_data.keywords.len() > self.validation.max_keywords
+        {
+            errors.push(format!(
+                "`keywords` must contain between 1 and {} entries, found {}",
+                self.validation.max_keywords,
+                document_data.keywords.len()
+            ));
+        }
+        if document_data
+            .keywords
+            .iter()
+            .any(|keyword| keyword.trim().is_empty())
+        {
+            errors.push("`keywords` must not contain empty entries".to_string());
+        }
+        if self.translation
+            && document_data
+                .summary_translation
+                .as_ref()
+                .is_none_or(|translation| translation.trim().is_empty())
+        {
+            errors.push("`summary_translation` is required".to_string());
+        }
+        if let Some(ref review) = self.review {
+            for field in &review.fields {
+                match document_data.confidence.get(field) {
+                    None => errors.push(format!("`confidence.{field}` is required")),
+                    Some(confidence) if !(0.0..=100.0).contains(confidence) => errors.push(
+                        format!("`confidence.{field}` must be between 0 and 100: {confidence}"),
+                    ),
+                    Some(_) => {}
+                }
+            }
+        }
+        for field in &self.fields {
+            self.check_field(field, &document_data, &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(document_data)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Validates the data of a document held back for review or approval, which
+    /// may have been corrected since, keeping the `outcome` of its first validation.
+    pub fn revalidate(
+        &self,
+        value: serde_json::Value,
+        outcome: ValidationOutcome,
+    ) -> Result<DocumentData, Vec<String>> {
+        let mut document_data = self.validate(value, &DocumentContext::default())?;
+        document_data.validation = outcome.merge(std::mem::take(&mut document_data.validation));
+
+        Ok(document_data)
+    }
+
+    fn check_field(
+        &self,
+        field: &FieldProfile,
+        document_data: &DocumentData,
+        errors: &mut Vec<String>,
+    ) {
+        let value = match document_data.extra.get(&field.name) {
+            Some(serde_json::Value::Null) | None => {
+                if field.required {
+                    errors.push(format!("`extra.{}` is required", field.name));
+                }
+                return;
+            }
+            Some(value) => value,
+        };
+        let valid = match field.field_type {
+            FieldType::String => value.is_string(),
+            FieldType::Number => value.is_number(),
+            FieldType::Integer => value.is_i64() || value.is_u64(),
+            FieldType::Boolean => value.is_boolean(),
+            FieldType::Date => value.as_str().is_some_and(is_date),
+        };
+        if !valid {
+            let expected = match field.field_type {
+                FieldType::String => "a string",
+                FieldType::Number => "a number",
+                FieldType::Integer => "an integer",
+                FieldType::Boolean => "a boolean",
+                FieldType::Date => "a date in YYYY-MM-DD",
+            };
+            errors.push(format!(
+                "`extra.{}` must be {expected}: {value}",
+                field.name
+            ));
+        }
+    }
+}
+
+pub fn make_repair_message(errors: &[String]) -> String {
+    format!(
+        "The document data you returned is invalid. Please call the function again with corrected values. The following problems were found:\n{}",
+        errors
+            .iter()
+            .map(|error| format!("* {error}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn make_value(date: &str, class: &str, title: &str, keywords: usize) -> serde_json::Value {
+        json!({
+            "summary": "Foo",
+            "class": class,
+            "source": "stadtwerke",
+            "keywords": vec!["foo"; keywords],
+            "title": title,
+            "date": date,
+        })
+    }
+
+    #[rstest]
+    #[case(make_value("2024-03-01", "invoice", "electricity-march", 2), 0)]
+    #[case(make_value("01.03.2024", "invoice", "electricity-march", 2), 1)]
+    #[case(make_value("2024-3-1", "invoice", "electricity-march", 2), 1)]
+    #[case(make_value("2024-03-01", "tax/invoice", "electricity march", 2), 2)]
+    #[case(make_value("2024-03-01", "invoice", "..", 0), 2)]
+    #[case(make_value("2024-03-01", "invoice", "electricity-march", 11), 1)]
+    #[case(json!({ "summary": "Foo" }), 1)]
+    fn test_validate(#[case] value: serde_json::Value, #[case] errors: usize) {
+        let result = Validator::default().validate(value, &DocumentContext::default());
+        assert_eq!(result.err().unwrap_or_default().len(), errors);
+    }
+
+    #[rstest]
+    #[case(json!({ "class": 90, "source": 85.5, "title": 75, "date": 99 }), 0)]
+    #[case(json!({ "class": 90, "source": -1, "title": 101, "date": 99 }), 2)]
+    #[case(json!({ "class": 90, "title": 75, "date": 99 }), 1)]
+    fn test_validate_confidence(#[case] confidence: serde_json::Value, #[case] errors: usize) {
+        let profile = Profile {
+            review: Some(ReviewProfile::default()),
+            ..Profile::default()
+        };
+        let mut value = make_value("2024-03-01", "invoice", "electricity-march", 2);
+        value["confidence"] = confidence;
+        let result = Validator::new(&profile).validate(value, &DocumentContext::default());
+        assert_eq!(result.err().unwrap_or_default().len(), errors);
+    }
+
+    #[rstest]
+    #[case("invoice", 0)]
+    #[case("other", 0)]
+    #[case("invoices", 1)]
+    fn test_validate_taxonomy(#[case] class: &str, #[case] errors: usize) {
+        let profile: Profile = serde_yaml::from_str(
+            "name: foo\ntaxonomy:\n  classes:\n    - name: invoice\n  other: true\npaths:\n  path: /home/foo\n  locations: {}\n",
+        )
+        .unwrap();
+        let value = make_value("2024-03-01", class, "electricity-march", 2);
+        let result = Validator::new(&profile).validate(value, &DocumentContext::default());
+        assert_eq!(result.err().unwrap_or_default().len(), errors);
+    }
+
+    #[rstest]
+    #[case(json!({}), 1)]
+    #[case(json!({ "total": 12.5, "due": "2024-04-01" }), 0)]
+    #[case(json!({ "total": "12.50 EUR", "due": "April" }), 2)]
+    fn test_validate_extra(#[case] extra: serde_json::Value, #[case] errors: usize) {
+        let profile: Profile = serde_yaml::from_str(
+            "name: foo\nfields:\n  - name: total\n    type: number\n    required: true\n  - name: due\n    type: date\npaths:\n  path: /home/foo\n  locations: {}\n",
+        )
+        .unwrap();
+        let mut value = make_value("2024-03-01", "invoice", "electricity-march", 2);
+        value["extra"] = extra;
+        let result = Validator::new(&profile).validate(value, &DocumentContext::default());
+        assert_eq!(result.err().unwrap_or_default().len(), errors);
+    }
+
+    #[rstest]
+    fn test_revalidate() {
+        let profile: Profile = serde_yaml::from_str(
+            "name: foo\naliases:\n  sources:\n    - canonical: stadtwerke\n      aliases: [swm]\npaths:\n  path: /home/foo\n  locations: {}\n",
+        )
+        .unwrap();
+        let mut value = make_value("2024-03-01", "invoice", "electricity-march", 2);
+        value["source"] = "SWM".into();
+        let outcome = ValidationOutcome {
+            rounds: 1,
+            repaired: vec!["`date` must be a valid date in YYYY-MM-DD: \"March\"".to_string()],
+            date_fallback: false,
+        };
+        let document_data = Validator::new(&profile)
+            .revalidate(value, outcome.clone())
+            .unwrap();
+        assert_eq!(document_data.source, "stadtwerke");
+        assert_eq!(document_data.validation, outcome);
+
+        let value = make_value("2024-03-01", "tax/invoice", "electricity-march", 2);
+        assert!(Validator::new(&profile)
+            .revalidate(value, ValidationOutcome::default())
+            .is_err());
+    }
+
+    #[rstest]
+    #[case(false, true, 1, "")]
+    #[case(true, false, 1, "")]
+    #[case(true, true, 0, "2024-03-02")]
+    fn test_date_fallback(
+        #[case] date_fallback: bool,
+        #[case] mtime: bool,
+        #[case] errors: usize,
+        #[case] date: &str,
+    ) {
+        let mut profile = Profile::default();
+        profile.hints.date_fallback = date_fallback;
+        let context = DocumentContext {
+            mtime: mtime.then(|| Local.with_ymd_and_hms(2024, 3, 2, 14, 31, 0).unwrap()),
+            ..DocumentContext::default()
+        };
+        let value = make_value("unknown", "invoice", "electricity-march", 2);
+        match Validator::new(&profile).validate(value, &context) {
+            Ok(document_data) => {
+                assert_eq!(errors, 0);
+                assert_eq!(document_data.date, date);
+                assert!(document_data.validation.date_fallback);
+            }
+            Err(result) => assert_eq!(result.len(), errors),
+        }
+    }
+}
@@ -42,11 +42,11 @@ impl WatcherLoop {
     pub async fn shutdown(self) -> Result<()> {
         self.shutdown_tx.send(()).await?;
 
-        Ok(self.join_handle.await??)
+        self.join_handle.await?
     }
 
     pub async fn wait(self) -> Result<()> {
-        Ok(self.join_handle.await??)
+        self.join_handle.await?
     }
 
     async fn run<H>(mut watcher: Watcher, mut event_handler: H) -> Result<()>
@@ -97,32 +97,25 @@ impl Watcher {
         let mut watcher: Box<dyn NotifyWatcher + Send + Unpin>;
         if polling {
             watcher = Box::new(PollWatcher::with_initial_scan(
-                move |event| match notify_tx.blocking_send(event) {
-                    Err(err) => {
+                move |event| {
+                    if let Err(err) = notify_tx.blocking_send(event) {
                         log::error!("Cannot send notify event: {err:?}");
                     }
-                    _ => {}
                 },
                 Config::default().with_poll_interval(Duration::from_secs(10)),
                 move |event| {
                     let scan_tx_dup = scan_tx.clone();
                     tokio::task::spawn(async move {
-                        match scan_tx_dup.send(event).await {
-                            Err(err) => {
-                                log::error!("Cannot send scan event: {err:?}");
-                            }
-                            _ => {}
-                        };
+                        if let Err(err) = scan_tx_dup.send(event).await {
+                            log::error!("Cannot send scan event: {err:?}");
+                        }
                     });
                 },
             )?);
         } else {
             watcher = Box::new(recommended_watcher(move |event| {
-                match notify_tx.blocking_send(event) {
-                    Err(err) => {
-                        log::error!("Cannot send event: {err:?}");
-                    }
-                    _ => {}
+                if let Err(err) = notify_tx.blocking_send(event) {
+                    log::error!("Cannot send event: {err:?}");
                 }
             })?);
         }
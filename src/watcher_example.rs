@@ -1,5 +1,4 @@
 use clap::Parser;
-use env_logger;
 use env_logger::{Builder, Env};
 use mrdocument::error::Result;
 use mrdocument::handler::EventHandler;
@@ -42,5 +41,5 @@ async fn main_log() -> Result<()> {
     log::info!("Watching {:?}", path);
     let watcher_loop = WatcherLoop::new(path, Handler {}, true).await?;
 
-    Ok(watcher_loop.wait().await?)
+    watcher_loop.wait().await
 }